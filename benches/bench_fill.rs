@@ -15,6 +15,12 @@ fn bench_fill(c: &mut Criterion) {
             let _ = fill_prompt::fill_template(black_box(&tpl), vars.clone()).unwrap();
         })
     });
+
+    c.bench_function("render_with large", |b| {
+        b.iter(|| {
+            let _ = fill_prompt::render_with(black_box(&tpl), black_box(&vars)).unwrap();
+        })
+    });
 }
 
 criterion_group!(benches, bench_fill);
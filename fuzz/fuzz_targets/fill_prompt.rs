@@ -1,8 +1,18 @@
 #![no_main]
 
+use fill_prompt::{FillError, FillOptions, RenderLimits};
 use libfuzzer_sys::fuzz_target;
 use std::collections::HashMap;
 
+// Small limits so the fuzzer reaches every limit error quickly.
+const LIMITS: RenderLimits = RenderLimits {
+    max_output_bytes: 4096,
+    max_placeholders: 64,
+    max_template_depth: 2,
+    max_recursion: 3,
+    max_substitutions: 256,
+};
+
 fn build_vars(lines: &str) -> HashMap<String, String> {
     let mut vars = HashMap::new();
     for line in lines.lines().take(16) {
@@ -31,6 +41,19 @@ fuzz_target!(|data: &[u8]| {
         if trimmed != template {
             let _ = fill_prompt::fill_template(trimmed, vars.clone());
         }
-        let _ = fill_prompt::fill_template(s, vars);
+        let _ = fill_prompt::fill_template(s, vars.clone());
+
+        // Same input with value expansion under tight limits: any limit error is
+        // fine, but a successful render must respect the output bound.
+        let options = FillOptions {
+            expand_values: true,
+            limits: LIMITS,
+            ..FillOptions::default()
+        };
+        match fill_prompt::fill_template_with(template, vars, &options) {
+            Ok(out) => assert!(out.len() <= LIMITS.max_output_bytes),
+            Err(FillError::OutputTooLarge(max)) => assert_eq!(max, LIMITS.max_output_bytes),
+            Err(_) => {}
+        }
     }
 });
@@ -3,29 +3,95 @@
 //! Usage:
 //!   fill-prompt-cli --template "<TEMPLATE>" [--var key=val]... [--vars file|inline]... [--out-dir DIR]
 //!   fill-prompt-cli --file <PATH>           [--var key=val]... [--vars file|inline]... [--out-dir DIR]
+//!   fill-prompt-cli extract --file PATH [--input FILE]
+//!   fill-prompt-cli diff OLD NEW [--format text|json]
+use std::borrow::Cow;
 use std::collections::{BTreeSet, HashMap};
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Context, Result};
+use zeroize::Zeroizing;
 
-use fill_prompt::validate::{validate_context, validate_short};
-use fill_prompt::vars::parse_vars_arg;
+use fill_prompt::diagnostics::{check_variables, lint_template, Suggestion, Typo};
+use fill_prompt::diff::diff_with_front_matter;
+use fill_prompt::escape::Escape;
+use fill_prompt::front_matter::{self, FrontMatter};
+use fill_prompt::normalize::{confusables, Normalization};
+#[cfg(feature = "redact")]
+use fill_prompt::redact::{RedactMode, Redactor};
+use fill_prompt::safety::scan_with_secrets;
+use fill_prompt::secret::{split_secret_key, ZeroizingVars};
+use fill_prompt::source_map::{Origin, SourceMap};
+use fill_prompt::validate::{Report, Schema, ValidationError, Violation};
+use fill_prompt::vars::{parse_vars_file, parse_vars_json, SETTINGS_KEY};
+use fill_prompt::{FillError, FillOptions, KeySyntax, Template, ValuePolicy};
 
 fn print_usage() {
     eprintln!("Usage:");
     eprintln!("  fill-prompt-cli --template <TEMPLATE> [--var key=val]... [--vars file|inline]... [--out-dir DIR]");
     eprintln!("  fill-prompt-cli --file <PATH>         [--var key=val]... [--vars file|inline]... [--out-dir DIR]");
+    eprintln!("  fill-prompt-cli extract (--template <TEMPLATE> | --file <PATH>) [--input FILE] [--escape M] [--indent] [--tagged] [--tag NAME]...");
+    eprintln!("                          recover the variables of a rendered output (FILE or stdin, one trailing newline");
+    eprintln!("                          ignored as printed by this tool), printed as JSON");
+    eprintln!("  fill-prompt-cli diff <OLD> <NEW> [--format text|json]");
+    eprintln!("                          compare two template files; fails when a change breaks existing vars files");
     eprintln!("Options:");
     eprintln!("  --template <TEMPLATE>   provide template string");
     eprintln!("  --file <PATH>           read template from file");
     eprintln!("  --var key=value         provide a variable (repeatable)");
+    eprintln!("  --secret-var key=value  provide a secret variable, never echoed (repeatable; or --var secret:key=value)");
     eprintln!("  --vars <file|inline>    load variables from file (json/yaml/toml) or inline JSON/TOML/YAML");
     eprintln!("  --out-dir <DIR>         write output file into DIR (creates it if missing)");
+    eprintln!("  --batch <FILE>          render once per line of FILE (one JSON object of variables per line), JSON lines out");
+    eprintln!("  --highlight             print the output with one colour per variable (ANSI)");
+    eprintln!("  --source-map <FILE>     write the origin (template text or variable) of every output range as JSON");
+    eprintln!(
+        "  --permissive-keys       accept any text as placeholder name (historical behaviour)"
+    );
+    eprintln!(
+        "  --strict                fail when a provided variable is not used by the template"
+    );
+    eprintln!("  --warn-unused           list variables not used by the template on stderr");
+    eprintln!("  --expand-values         fill {{{{...}}}} inside values with the same variables (cycles are errors)");
+    eprintln!("  --max-depth <N>         nesting limit for --expand-values (default 8)");
+    eprintln!("  --value-policy allow|escape|reject  what to do with {{{{ or }}}} inside values (default allow)");
+    eprintln!("  --escape <MODE>         escape values: none|json|yaml|shell|xml|markdown (default from a FILE.<mode>.tpl name)");
+    eprintln!(
+        "  --indent                indent continuation lines of values to the placeholder column"
+    );
+    eprintln!("  --tagged                wrap every value in <name>...</name>");
+    eprintln!("  --tag <NAME>            wrap the value of NAME in <NAME>...</NAME> (repeatable)");
+    eprintln!("  --safety warn|deny      scan values for prompt-injection patterns, warn or fail");
+    eprintln!("  --max-output-bytes <N>  fail instead of producing more than N bytes");
+    eprintln!("  --max-placeholders <N>  fail on templates with more than N placeholders");
+    eprintln!("  --normalize nfc|nfkc    normalise template and variables, strip zero-width chars, report confusables");
+    eprintln!("  --redact mask|hash|fail redact e-mails, phones, IBANs, API keys... in values (feature redact)");
+    eprintln!("  --redact-config <FILE>  extra redaction patterns and settings (json/yaml/toml)");
+    eprintln!("  --schema <FILE>         validation rules per variable (toml/yaml, same fields as the front matter)");
+    eprintln!("  --report-format text|json  list validation failures as text on stderr (default) or as JSON on stdout,");
+    eprintln!("                          (missing variables, --strict, --safety deny included)");
+    eprintln!("  --describe              list the template variables (front matter descriptions, types, defaults) and exit");
     eprintln!("  --help, -h              show this message");
 }
 
+/// Réaction aux valeurs suspectes (`--safety`).
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SafetyMode {
+    Warn,
+    Deny,
+}
+
+/// Que faire des variables fournies mais absentes du template.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum UnusedVars {
+    Ignore,
+    Warn,
+    Deny,
+}
+
 fn parse_kv(s: &str) -> Result<(String, String)> {
     let mut parts = s.splitn(2, '=');
     match (parts.next(), parts.next()) {
@@ -34,44 +100,471 @@ fn parse_kv(s: &str) -> Result<(String, String)> {
     }
 }
 
-/// Extract all `{{placeholder}}` keys present in the template (best-effort; errors like unclosed braces are left to the filler).
-fn extract_placeholders(template: &str) -> BTreeSet<String> {
-    let mut set = BTreeSet::new();
-    let bytes = template.as_bytes();
-    let mut i = 0usize;
-    while i + 1 < bytes.len() {
-        if bytes[i] == b'{' && bytes[i + 1] == b'{' {
-            i += 2;
-            let start = i;
-            while i + 1 < bytes.len() && !(bytes[i] == b'}' && bytes[i + 1] == b'}') {
-                i += 1;
+/// Affiche `title (n): a, b` puis une ligne par suggestion ("did you mean").
+fn print_names(title: &str, names: &[Suggestion], typos: &[Typo]) {
+    let list: Vec<&str> = names.iter().map(|s| s.name.as_str()).collect();
+    eprintln!("{} ({}): {}", title, list.len(), list.join(", "));
+    for s in names {
+        let typo = typos
+            .iter()
+            .find(|t| t.placeholder == s.name || t.provided == s.name);
+        match (typo, &s.did_you_mean) {
+            (Some(t), _) => eprintln!(
+                "  likely typo: template uses `{}`, variables provide `{}`",
+                t.placeholder, t.provided
+            ),
+            (None, Some(alt)) => eprintln!("  `{}`: did you mean `{}`?", s.name, alt),
+            (None, None) => {}
+        }
+    }
+}
+
+/// Applique `--redact` / `--redact-config` et affiche ce qui a été masqué.
+#[cfg(feature = "redact")]
+fn redact_vars(
+    vars: &HashMap<String, String>,
+    mode: Option<&str>,
+    config: Option<&Path>,
+) -> Result<ZeroizingVars> {
+    let mode = mode
+        .map(|m| RedactMode::from_name(m).ok_or_else(|| anyhow!("invalid --redact '{}'", m)))
+        .transpose()?;
+    let mut redactor = match config {
+        Some(path) => Redactor::from_config_file(path)
+            .with_context(|| format!("failed to load --redact-config '{}'", path.display()))?,
+        None => Redactor::new(mode.unwrap_or_default()),
+    };
+    if let Some(mode) = mode {
+        redactor.set_mode(mode);
+    }
+    let (redacted, redactions) = redactor
+        .redact_vars(vars)
+        .map_err(|e| anyhow!("redaction failed: {}", e))?;
+    if !redactions.is_empty() {
+        eprintln!("Redacted values ({}):", redactions.len());
+        for r in &redactions {
+            eprintln!("  {}", r);
+        }
+    }
+    Ok(redacted)
+}
+
+#[cfg(not(feature = "redact"))]
+fn redact_vars(
+    _vars: &HashMap<String, String>,
+    _mode: Option<&str>,
+    _config: Option<&Path>,
+) -> Result<ZeroizingVars> {
+    Err(anyhow!(
+        "feature \"redact\" non activée. Recompilez avec `--features redact`."
+    ))
+}
+
+/// Rend chaque ligne en parallèle (feature `parallel`).
+#[cfg(feature = "parallel")]
+fn render_rows(template: &Template, rows: &[ZeroizingVars]) -> Vec<Result<String, FillError>> {
+    use rayon::prelude::*;
+    template.render_batch(rows.par_iter())
+}
+
+/// Rend chaque ligne l'une après l'autre (sans la feature `parallel`).
+#[cfg(not(feature = "parallel"))]
+fn render_rows(template: &Template, rows: &[ZeroizingVars]) -> Vec<Result<String, FillError>> {
+    rows.iter().map(|row| template.render_lookup(row)).collect()
+}
+
+/// Contrôles d'une exécution simple, rejoués pour chaque ligne de `--batch`.
+struct RowChecks<'a> {
+    template: &'a Template,
+    front: &'a FrontMatter,
+    schema: &'a Schema,
+    common: &'a ZeroizingVars,
+}
+
+impl RowChecks<'_> {
+    /// Variables d'une ligne complétées par les variables communes et les
+    /// valeurs par défaut, normalisées puis vérifiées ; l'erreur ne cite que
+    /// des noms (une ligne peut contenir des secrets).
+    fn prepare(&self, line: &str) -> Result<ZeroizingVars, String> {
+        let parsed = parse_vars_json(line).map_err(|e| format!("invalid JSON object: {:#}", e))?;
+        if !parsed.settings.tagged.is_empty() {
+            return Err(format!(
+                "the `{}` table is not supported in --batch rows",
+                SETTINGS_KEY
+            ));
+        }
+        let mut vars = ZeroizingVars(self.common.0.clone());
+        vars.extend(parsed.vars);
+        self.front.apply_defaults(&mut vars);
+        let normalize = self.template.options().normalize;
+        if let Cow::Owned(normalized) = normalize.apply_vars(&vars).map_err(|e| e.to_string())? {
+            vars = ZeroizingVars(normalized);
+        }
+
+        let check = check_variables(self.template, &vars).map_err(|e| e.to_string())?;
+        if !check.missing.is_empty() {
+            let names: Vec<&str> = check.missing.iter().map(|s| s.name.as_str()).collect();
+            return Err(format!("missing variables: {}", names.join(", ")));
+        }
+        let required = self.front.missing(&vars);
+        if !required.is_empty() {
+            return Err(format!(
+                "missing required variables (front matter): {}",
+                required.join(", ")
+            ));
+        }
+        let report = self.schema.report(&vars);
+        if !report.is_valid() {
+            let violations: Vec<String> =
+                report.violations().iter().map(|v| v.to_string()).collect();
+            return Err(format!(
+                "invalid variable values: {}",
+                violations.join("; ")
+            ));
+        }
+        Ok(vars)
+    }
+}
+
+/// Mode `--batch` : une ligne JSON en sortie par ligne d'entrée, erreurs comprises.
+fn run_batch(checks: &RowChecks<'_>, path: &Path, out_path: Option<&Path>) -> Result<()> {
+    let input = Zeroizing::new(
+        fs::read_to_string(path)
+            .with_context(|| format!("failed to read --batch '{}'", path.display()))?,
+    );
+    // (numéro de ligne, erreur des contrôles) ; les lignes valides vont dans `rows`
+    let mut lines = Vec::new();
+    let mut rows = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match checks.prepare(line) {
+            Ok(vars) => {
+                rows.push(vars);
+                lines.push((i + 1, None));
             }
-            if i + 1 >= bytes.len() {
-                break; // laisser l'erreur au remplissage
+            Err(e) => lines.push((i + 1, Some(e))),
+        }
+    }
+
+    let mut rendered = render_rows(checks.template, &rows).into_iter();
+    let outcomes: Vec<(usize, Result<Zeroizing<String>, String>)> = lines
+        .into_iter()
+        .map(|(line, checked)| {
+            let outcome = match checked {
+                Some(e) => Err(e),
+                None => match rendered.next().expect("one result per checked row") {
+                    Ok(output) => Ok(Zeroizing::new(output)),
+                    Err(e) => Err(e.to_string()),
+                },
+            };
+            (line, outcome)
+        })
+        .collect();
+    let mut out = Zeroizing::new(String::new());
+    let mut failed = 0;
+    for (line, outcome) in &outcomes {
+        let (field, text) = match outcome {
+            Ok(output) => ("output", output.as_str()),
+            Err(e) => {
+                failed += 1;
+                ("error", e.as_str())
             }
-            let key = template[start..i].trim();
-            if !key.is_empty() {
-                set.insert(key.to_string());
+        };
+        out.push_str(&format!("{{\"line\":{},\"{}\":\"", line, field));
+        Escape::Json.push(&mut out, text);
+        out.push_str("\"}\n");
+    }
+
+    match out_path {
+        Some(p) => {
+            fs::write(p, out.as_bytes())
+                .with_context(|| format!("failed to write output file '{}'", p.display()))?;
+            eprintln!("Wrote {} rendered rows to: {}", outcomes.len(), p.display());
+        }
+        None => print!("{}", out.as_str()),
+    }
+    if failed > 0 {
+        eprintln!("Batch: {} of {} rows failed", failed, outcomes.len());
+        anyhow::bail!("batch rendering failed");
+    }
+    Ok(())
+}
+
+/// Couleurs ANSI attribuées aux variables, dans l'ordre alphabétique des noms.
+const HIGHLIGHT_COLOURS: [&str; 6] = [
+    "\x1b[31m", "\x1b[32m", "\x1b[33m", "\x1b[34m", "\x1b[35m", "\x1b[36m",
+];
+const ANSI_RESET: &str = "\x1b[0m";
+
+/// `--highlight` : la sortie sur stdout, chaque valeur dans la couleur de sa
+/// variable ; la légende va sur stderr.
+fn print_highlighted(output: &str, map: &SourceMap) {
+    let keys: BTreeSet<&str> = map
+        .mappings()
+        .iter()
+        .filter_map(|m| match &m.origin {
+            Origin::Variable { key, .. } => Some(key.as_str()),
+            Origin::Literal { .. } => None,
+        })
+        .collect();
+    let colour = |key: &str| {
+        let i = keys.iter().position(|k| *k == key).unwrap_or(0);
+        HIGHLIGHT_COLOURS[i % HIGHLIGHT_COLOURS.len()]
+    };
+    let mut text = Zeroizing::new(String::with_capacity(output.len()));
+    for m in map.mappings() {
+        let part = &output[m.output.clone()];
+        match &m.origin {
+            Origin::Variable { key, .. } if !part.is_empty() => {
+                text.push_str(colour(key));
+                text.push_str(part);
+                text.push_str(ANSI_RESET);
             }
-            i += 2; // passer "}}"
-        } else {
-            // avancer d'un char UTF-8
-            if let Some(ch) = template[i..].chars().next() {
-                i += ch.len_utf8();
-            } else {
-                break;
+            _ => text.push_str(part),
+        }
+    }
+    println!("{}", text.as_str());
+    for key in &keys {
+        eprintln!("  {}{}{}", colour(key), key, ANSI_RESET);
+    }
+}
+
+/// `--describe` : les variables du template, avec ce qu'en dit le front matter.
+fn print_description(template: &Template, front: &FrontMatter) {
+    let mut names: BTreeSet<&str> = template.keys();
+    names.extend(front.variables.keys().map(String::as_str));
+    for name in names {
+        let Some(spec) = front.variables.get(name) else {
+            println!("{} (undeclared)", name);
+            continue;
+        };
+        let mut details = vec![spec.kind.name().to_string()];
+        if spec.required {
+            details.push("required".to_string());
+        }
+        if let (Some(default), false) = (&spec.default, spec.secret) {
+            details.push(format!("default {:?}", default));
+        }
+        if spec.secret {
+            details.push("secret".to_string());
+        }
+        match &spec.description {
+            Some(text) => println!("{} ({}): {}", name, details.join(", "), text),
+            None => println!("{} ({})", name, details.join(", ")),
+        }
+        if let Some(example) = &spec.example {
+            println!("    example: {}", example);
+        }
+    }
+}
+
+/// Sous-commande `extract` : retrouve les variables d'une sortie déjà rendue
+/// et les affiche en objet JSON (clés triées).
+fn run_extract(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut template: Option<String> = None;
+    let mut template_file_path: Option<PathBuf> = None;
+    let mut input: Option<PathBuf> = None;
+    let mut options = FillOptions::default();
+    let mut escape: Option<Escape> = None;
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "--template" => {
+                let t = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--template requires an argument"))?;
+                template = Some(t);
+            }
+            "--file" => {
+                let p = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--file requires a path"))?;
+                let s = fs::read_to_string(&p)
+                    .with_context(|| format!("failed to read file '{}'", p))?;
+                template = Some(s);
+                template_file_path = Some(PathBuf::from(p));
+            }
+            "--input" => {
+                let p = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--input requires a file path"))?;
+                input = Some(PathBuf::from(p));
+            }
+            "--escape" => {
+                let m = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--escape requires a mode"))?;
+                escape = match m.as_str() {
+                    "none" => Some(Escape::None),
+                    other => Some(
+                        Escape::from_name(other)
+                            .ok_or_else(|| anyhow!("invalid --escape '{}'", other))?,
+                    ),
+                };
+            }
+            "--indent" => {
+                options.indent = true;
+            }
+            "--tagged" => {
+                options.tag_all = true;
+            }
+            "--tag" => {
+                let name = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--tag requires a variable name"))?;
+                options.tagged.insert(name);
+            }
+            other => {
+                print_usage();
+                return Err(anyhow!("unknown extract arg {}", other));
+            }
+        }
+    }
+    let template = template.ok_or_else(|| anyhow!("no template provided"))?;
+    options.escape = escape
+        .or_else(|| template_file_path.as_deref().map(Escape::from_path))
+        .unwrap_or_default();
+    let (front, body) =
+        front_matter::parse(&template).map_err(|e| anyhow!("invalid front matter: {:#}", e))?;
+    if let Some(front) = front {
+        front.apply_options(&mut options);
+    }
+    let template =
+        Template::parse_with(body, &options).map_err(|e| anyhow!("invalid template: {}", e))?;
+
+    let rendered = match &input {
+        Some(path) => fs::read_to_string(path)
+            .with_context(|| format!("failed to read --input '{}'", path.display()))?,
+        None => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .context("failed to read the rendered output from stdin")?;
+            text
+        }
+    };
+    // la sortie imprimée par le CLI finit par le saut de ligne de `println!` :
+    // on l'essaie d'abord sans, puis le texte exact (fichier de --out-dir)
+    let vars = match rendered.strip_suffix('\n') {
+        Some(printed) => template
+            .extract(printed)
+            .or_else(|e| template.extract(&rendered).map_err(|_| e)),
+        None => template.extract(&rendered),
+    }
+    .map_err(|e| anyhow!("failed to extract variables: {}", e))?;
+
+    let mut names: Vec<&String> = vars.keys().collect();
+    names.sort();
+    let mut out = String::from("{");
+    for (i, name) in names.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push('"');
+        Escape::Json.push(&mut out, name);
+        out.push_str("\":\"");
+        Escape::Json.push(&mut out, &vars[name]);
+        out.push('"');
+    }
+    out.push('}');
+    println!("{}", out);
+    Ok(())
+}
+
+/// Sous-commande `diff` : changements entre deux versions d'un template,
+/// échec s'il y en a un de cassant (pour la CI).
+fn run_diff(args: impl Iterator<Item = String>) -> Result<()> {
+    let mut paths = Vec::new();
+    let mut json = false;
+    let mut args = args.peekable();
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "--format" => {
+                let f = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--format requires text|json"))?;
+                json = match f.as_str() {
+                    "text" => false,
+                    "json" => true,
+                    other => return Err(anyhow!("invalid --format '{}'", other)),
+                };
+            }
+            other if other.starts_with("--") => {
+                print_usage();
+                return Err(anyhow!("unknown diff arg {}", other));
             }
+            path => paths.push(PathBuf::from(path)),
+        }
+    }
+    let [old, new] = paths.as_slice() else {
+        print_usage();
+        anyhow::bail!("diff requires two template files");
+    };
+    let parse = |path: &Path| -> Result<(Template, FrontMatter)> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("failed to read file '{}'", path.display()))?;
+        let (front, body) = front_matter::parse(&source)
+            .with_context(|| format!("invalid front matter in '{}'", path.display()))?;
+        let template = Template::parse(body)
+            .map_err(|e| anyhow!("invalid template '{}': {}", path.display(), e))?;
+        Ok((template, front.unwrap_or_default()))
+    };
+    let (old, old_front) = parse(old)?;
+    let (new, new_front) = parse(new)?;
+    let changes = diff_with_front_matter((&old, &old_front), (&new, &new_front));
+
+    if json {
+        println!("{}", changes.to_json());
+    } else {
+        for change in changes.changes() {
+            let label = if change.is_breaking() {
+                "BREAKING    "
+            } else {
+                "non-breaking"
+            };
+            println!("{} {}", label, change);
         }
+        let breaking = changes.changes().iter().filter(|c| c.is_breaking()).count();
+        println!("{} changes, {} breaking", changes.changes().len(), breaking);
+    }
+    if changes.is_breaking() {
+        anyhow::bail!("breaking template changes");
     }
-    set
+    Ok(())
 }
 
 fn main() -> Result<()> {
-    let mut args = env::args().skip(1);
+    let mut args = env::args().skip(1).peekable();
+    match args.peek().map(String::as_str) {
+        Some("extract") => {
+            args.next();
+            return run_extract(args);
+        }
+        Some("diff") => {
+            args.next();
+            return run_diff(args);
+        }
+        _ => {}
+    }
+    // valeurs effacées à la libération, donc sur tous les chemins de sortie
+    let mut vars_map = ZeroizingVars::default();
     let mut template: Option<String> = None;
     let mut template_file_path: Option<PathBuf> = None; // pour générer un nom de fichier de sortie
-    let mut vars_map: HashMap<String, String> = HashMap::new();
+    let mut secrets: BTreeSet<String> = BTreeSet::new(); // noms des variables secrètes
     let mut out_dir: Option<PathBuf> = None;
+    let mut options = FillOptions::default();
+    let mut unused_vars = UnusedVars::Ignore;
+    let mut escape: Option<Escape> = None;
+    let mut safety: Option<SafetyMode> = None;
+    let mut redact_mode: Option<String> = None;
+    let mut redact_config: Option<PathBuf> = None;
+    let mut batch: Option<PathBuf> = None;
+    let mut highlight = false;
+    let mut describe = false;
+    let mut source_map_path: Option<PathBuf> = None;
+    let mut schema_path: Option<PathBuf> = None;
+    let mut json_report = false;
 
     while let Some(a) = args.next() {
         match a.as_str() {
@@ -91,21 +584,45 @@ fn main() -> Result<()> {
                 template_file_path = Some(PathBuf::from(p));
             }
             "--var" => {
-                let kv = args
-                    .next()
-                    .ok_or_else(|| anyhow!("--var requires key=value"))?;
+                let kv = Zeroizing::new(
+                    args.next()
+                        .ok_or_else(|| anyhow!("--var requires key=value"))?,
+                );
                 let (k, v) = parse_kv(&kv)?;
-                vars_map.insert(k, v); // dernière occurrence gagne
+                let (name, secret) = split_secret_key(&k);
+                if secret {
+                    secrets.insert(name.to_string());
+                }
+                vars_map.insert(name.to_string(), v); // dernière occurrence gagne
+            }
+            "--secret-var" => {
+                let kv = Zeroizing::new(
+                    args.next()
+                        .ok_or_else(|| anyhow!("--secret-var requires key=value"))?,
+                );
+                let (k, v) = parse_kv(&kv)?;
+                let (name, _) = split_secret_key(&k);
+                secrets.insert(name.to_string());
+                vars_map.insert(name.to_string(), v);
             }
             "--vars" => {
-                let arg = args
-                    .next()
-                    .ok_or_else(|| anyhow!("--vars requires a file path or inline value"))?;
-                let parsed = parse_vars_arg(&arg)
-                    .with_context(|| format!("failed to parse --vars '{}'", arg))?;
-                for (k, v) in parsed {
+                let arg = Zeroizing::new(
+                    args.next()
+                        .ok_or_else(|| anyhow!("--vars requires a file path or inline value"))?,
+                );
+                // une valeur inline peut contenir des secrets : on ne la répète pas
+                let parsed = parse_vars_file(&arg).with_context(|| {
+                    if Path::new(arg.as_str()).is_file() {
+                        format!("failed to parse --vars '{}'", arg.as_str())
+                    } else {
+                        "failed to parse inline --vars".to_string()
+                    }
+                })?;
+                secrets.extend(parsed.secrets);
+                for (k, v) in parsed.vars {
                     vars_map.insert(k, v); // dernière occurrence gagne
                 }
+                options.tagged.extend(parsed.settings.tagged);
             }
             "--out-dir" => {
                 let d = args
@@ -113,6 +630,142 @@ fn main() -> Result<()> {
                     .ok_or_else(|| anyhow!("--out-dir requires a directory path"))?;
                 out_dir = Some(PathBuf::from(d));
             }
+            "--batch" => {
+                let p = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--batch requires a file path"))?;
+                batch = Some(PathBuf::from(p));
+            }
+            "--highlight" => {
+                highlight = true;
+            }
+            "--source-map" => {
+                let p = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--source-map requires a file path"))?;
+                source_map_path = Some(PathBuf::from(p));
+            }
+            "--permissive-keys" => {
+                options.key_syntax = KeySyntax::Permissive;
+            }
+            "--expand-values" => {
+                options.expand_values = true;
+            }
+            "--max-depth" => {
+                let n = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--max-depth requires a number"))?;
+                options.limits.max_recursion = n
+                    .parse()
+                    .with_context(|| format!("invalid --max-depth '{}'", n))?;
+            }
+            "--max-output-bytes" => {
+                let n = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--max-output-bytes requires a number"))?;
+                options.limits.max_output_bytes = n
+                    .parse()
+                    .with_context(|| format!("invalid --max-output-bytes '{}'", n))?;
+            }
+            "--max-placeholders" => {
+                let n = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--max-placeholders requires a number"))?;
+                options.limits.max_placeholders = n
+                    .parse()
+                    .with_context(|| format!("invalid --max-placeholders '{}'", n))?;
+            }
+            "--value-policy" => {
+                let p = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--value-policy requires allow|escape|reject"))?;
+                options.value_policy = match p.as_str() {
+                    "allow" => ValuePolicy::Allow,
+                    "escape" => ValuePolicy::Escape,
+                    "reject" => ValuePolicy::Reject,
+                    other => return Err(anyhow!("invalid --value-policy '{}'", other)),
+                };
+            }
+            "--escape" => {
+                let m = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--escape requires a mode"))?;
+                escape = match m.as_str() {
+                    "none" => Some(Escape::None),
+                    other => Some(
+                        Escape::from_name(other)
+                            .ok_or_else(|| anyhow!("invalid --escape '{}'", other))?,
+                    ),
+                };
+            }
+            "--indent" => {
+                options.indent = true;
+            }
+            "--tagged" => {
+                options.tag_all = true;
+            }
+            "--tag" => {
+                let name = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--tag requires a variable name"))?;
+                options.tagged.insert(name);
+            }
+            "--safety" => {
+                let m = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--safety requires warn|deny"))?;
+                safety = match m.as_str() {
+                    "warn" => Some(SafetyMode::Warn),
+                    "deny" => Some(SafetyMode::Deny),
+                    other => return Err(anyhow!("invalid --safety '{}'", other)),
+                };
+            }
+            "--normalize" => {
+                let n = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--normalize requires nfc|nfkc"))?;
+                options.normalize = Normalization::from_name(&n)
+                    .ok_or_else(|| anyhow!("invalid --normalize '{}'", n))?;
+            }
+            "--redact" => {
+                let m = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--redact requires mask|hash|fail"))?;
+                redact_mode = Some(m);
+            }
+            "--redact-config" => {
+                let p = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--redact-config requires a file path"))?;
+                redact_config = Some(PathBuf::from(p));
+            }
+            "--strict" => {
+                unused_vars = UnusedVars::Deny;
+            }
+            "--warn-unused" => {
+                if unused_vars == UnusedVars::Ignore {
+                    unused_vars = UnusedVars::Warn;
+                }
+            }
+            "--describe" => {
+                describe = true;
+            }
+            "--schema" => {
+                let p = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--schema requires a file path"))?;
+                schema_path = Some(PathBuf::from(p));
+            }
+            "--report-format" => {
+                let f = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--report-format requires text|json"))?;
+                json_report = match f.as_str() {
+                    "text" => false,
+                    "json" => true,
+                    other => return Err(anyhow!("invalid --report-format '{}'", other)),
+                };
+            }
             "--help" | "-h" => {
                 print_usage();
                 return Ok(());
@@ -133,30 +786,233 @@ fn main() -> Result<()> {
         }
     };
 
-    // Pré-check facultatif : lister toutes les variables manquantes d’un coup.
-    let needed = extract_placeholders(&template);
-    let have: BTreeSet<String> = vars_map.keys().cloned().collect();
-    let missing: Vec<_> = needed.difference(&have).cloned().collect();
-    if !missing.is_empty() {
-        eprintln!(
-            "Missing variables ({}): {}",
-            missing.len(),
-            missing.join(", ")
+    // Le mode batch ne fait pas ces analyses des valeurs : refuser plutôt que les ignorer
+    if batch.is_some()
+        && (safety.is_some()
+            || redact_mode.is_some()
+            || redact_config.is_some()
+            || unused_vars != UnusedVars::Ignore)
+    {
+        anyhow::bail!(
+            "--batch cannot be combined with --safety, --redact or --strict/--warn-unused"
         );
-        anyhow::bail!("incomplete variable set");
+    }
+    if batch.is_some() && (highlight || source_map_path.is_some()) {
+        anyhow::bail!("--batch cannot be combined with --highlight or --source-map");
+    }
+    if highlight && out_dir.is_some() {
+        anyhow::bail!("--highlight prints to the terminal and cannot be combined with --out-dir");
+    }
+
+    // Échappement : option explicite, sinon déduit de l'extension du fichier
+    options.escape = escape
+        .or_else(|| template_file_path.as_deref().map(Escape::from_path))
+        .unwrap_or_default();
+
+    // Front matter : variables déclarées (balises, secrets, valeurs par défaut)
+    let (front, body) =
+        front_matter::parse(&template).map_err(|e| anyhow!("invalid front matter: {:#}", e))?;
+    let front = front.unwrap_or_default();
+    front.apply_options(&mut options);
+    secrets.extend(front.secrets().map(str::to_string));
+    front.apply_defaults(&mut vars_map);
+
+    // Analyse du template (erreurs de syntaxe remontées avant tout le reste)
+    let template =
+        Template::parse_with(body, &options).map_err(|e| anyhow!("invalid template: {}", e))?;
+
+    if describe {
+        print_description(&template, &front);
+        return Ok(());
+    }
+
+    // Avertissements sur le template lui-même (non bloquants)
+    for lint in lint_template(&template) {
+        eprintln!("warning: {}", lint);
+    }
+    if !front.variables.is_empty() {
+        for key in template.keys() {
+            if !front.variables.contains_key(key) {
+                eprintln!("warning: `{}` is not declared in the front matter", key);
+            }
+        }
+    }
+
+    // Normalisation Unicode : clés et valeurs (le template l'est déjà), puis caractères trompeurs
+    if options.normalize != Normalization::None {
+        // une seule fois : le rendu et les diagnostics empruntent ensuite la
+        // table déjà normalisée ; l'ancienne est effacée en étant remplacée
+        let normalized = options
+            .normalize
+            .apply_vars(&vars_map)
+            .map_err(|e| anyhow!("invalid variables: {}", e))?;
+        if let Cow::Owned(normalized) = normalized {
+            vars_map = ZeroizingVars(normalized);
+        }
+        secrets = secrets
+            .iter()
+            .map(|s| options.normalize.apply(s).into_owned())
+            .collect();
+        for c in confusables(template.source()) {
+            eprintln!("warning: confusable character in template: {}", c);
+        }
+        let mut names: Vec<&String> = vars_map.keys().collect();
+        names.sort();
+        for name in names {
+            for c in confusables(&vars_map[name]) {
+                eprintln!("warning: confusable character in `{}`: {}", name, c);
+            }
+        }
+    }
+
+    // Validation : limites historiques, puis front matter, puis --schema
+    // (chaque source remplace les règles des variables qu'elle déclare)
+    let mut schema = Schema::builtin();
+    schema.merge(Schema::from_front_matter(&front));
+    if let Some(path) = &schema_path {
+        schema.merge(Schema::load(path)?);
+    }
+
+    // Mode batch : chaque ligne passe les mêmes contrôles et signale ses propres erreurs
+    if let Some(path) = batch {
+        let out_path = match &out_dir {
+            Some(dir) => {
+                fs::create_dir_all(dir).with_context(|| {
+                    format!("failed to create output directory '{}'", dir.display())
+                })?;
+                let base = template_file_path
+                    .as_deref()
+                    .and_then(|p| p.file_stem())
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("output");
+                Some(dir.join(format!("{}-batch.jsonl", base)))
+            }
+            None => None,
+        };
+        let checks = RowChecks {
+            template: &template,
+            front: &front,
+            schema: &schema,
+            common: &vars_map,
+        };
+        return run_batch(&checks, &path, out_path.as_deref());
     }
 
-    // Validation s'il y a des clés cibles
-    if let Some(short) = vars_map.get("short_description") {
-        validate_short(short).map_err(|e| anyhow!("short_description validation failed: {}", e))?;
+    // Pré-check : lister toutes les variables manquantes d’un coup, avec suggestions.
+    let mut check =
+        check_variables(&template, &vars_map).map_err(|e| anyhow!("invalid variables: {}", e))?;
+    // une variable déclarée n'est pas une faute de frappe, même hors du template
+    check
+        .unused
+        .retain(|s| !front.variables.contains_key(&s.name));
+    let typos = check.typos();
+    // en JSON, les variables manquantes rejoignent le rapport de validation
+    let mut report = Report::default();
+    let required = front.missing(&vars_map);
+    if json_report {
+        let missing = check.missing.iter().map(|s| s.name.as_str());
+        let mut names: Vec<&str> = missing.chain(required).collect();
+        names.sort_unstable();
+        names.dedup();
+        for name in names {
+            report.push(Violation {
+                variable: name.to_string(),
+                rule: "required",
+                error: ValidationError::Missing,
+            });
+        }
+    } else {
+        if !check.missing.is_empty() {
+            print_names("Missing variables", &check.missing, &typos);
+            anyhow::bail!("incomplete variable set");
+        }
+        if !required.is_empty() {
+            eprintln!("Missing required variables (front matter):");
+            for name in required {
+                eprintln!("  - {}", name);
+            }
+            anyhow::bail!("incomplete variable set");
+        }
     }
-    if let Some(ctx) = vars_map.get("context_paragraph") {
-        validate_context(ctx).map_err(|e| anyhow!("context_paragraph validation failed: {}", e))?;
+
+    // Variables inutilisées (souvent une faute de frappe dans une clé)
+    if unused_vars == UnusedVars::Deny && json_report {
+        for s in &check.unused {
+            report.push(Violation {
+                variable: s.name.clone(),
+                rule: "unused",
+                error: ValidationError::Unused,
+            });
+        }
+    } else if unused_vars != UnusedVars::Ignore && !check.unused.is_empty() {
+        print_names("Unused variables", &check.unused, &typos);
+        if unused_vars == UnusedVars::Deny {
+            anyhow::bail!("unused variables (--strict)");
+        }
     }
 
-    // Remplissage
-    let output = fill_prompt::fill_template(&template, vars_map.into_iter())
-        .map_err(|e| anyhow!("failed to fill template: {}", e))?;
+    // Recherche d'injections de prompt dans les valeurs
+    if let Some(mode) = safety {
+        let findings = scan_with_secrets(vars_map.iter(), &secrets);
+        if mode == SafetyMode::Deny && json_report {
+            // le genre de trouvaille seulement : le rapport ne reprend aucune valeur
+            for f in findings {
+                report.push(Violation {
+                    variable: f.variable,
+                    rule: "safety",
+                    error: ValidationError::Suspicious(f.kind.to_string()),
+                });
+            }
+        } else if !findings.is_empty() {
+            eprintln!("Safety findings ({}):", findings.len());
+            for f in &findings {
+                eprintln!("  {}", f);
+            }
+            if mode == SafetyMode::Deny {
+                anyhow::bail!("suspicious variable values (--safety deny)");
+            }
+        }
+    }
+
+    // Validation (tous les échecs d'un coup, pour qu'un formulaire les signale ensemble)
+    report.merge(schema.report(&vars_map));
+    if !report.is_valid() {
+        if json_report {
+            println!("{}", report.to_json());
+        } else {
+            eprintln!("Validation failed ({}):", report.violations().len());
+            for v in report.violations() {
+                eprintln!("  {}", v);
+            }
+        }
+        anyhow::bail!("invalid variable values");
+    }
+
+    // Masquage des données personnelles / secrets juste avant le rendu : les
+    // contrôles précédents portent sur les valeurs saisies
+    if redact_mode.is_some() || redact_config.is_some() {
+        vars_map = redact_vars(&vars_map, redact_mode.as_deref(), redact_config.as_deref())?;
+    }
+
+    // Remplissage (avec la carte des origines si elle est demandée)
+    let (output, source_map) = if highlight || source_map_path.is_some() {
+        let (output, map) = template
+            .render_with_source_map(&vars_map)
+            .map_err(|e| anyhow!("failed to fill template: {}", e))?;
+        (Zeroizing::new(output), Some(map))
+    } else {
+        let output = template
+            .render(&vars_map)
+            .map_err(|e| anyhow!("failed to fill template: {}", e))?;
+        (Zeroizing::new(output), None)
+    };
+
+    // La carte ne contient que des positions et des noms, jamais de valeurs
+    if let (Some(path), Some(map)) = (&source_map_path, &source_map) {
+        fs::write(path, map.to_json())
+            .with_context(|| format!("failed to write source map '{}'", path.display()))?;
+        eprintln!("Wrote source map to: {}", path.display());
+    }
 
     // Écriture conditionnelle
     if let Some(dir) = out_dir {
@@ -176,10 +1032,13 @@ fn main() -> Result<()> {
         fs::write(&out_path, output.as_bytes())
             .with_context(|| format!("failed to write output file '{}'", out_path.display()))?;
         eprintln!("Wrote filled template to: {}", out_path.display());
+    } else if let (true, Some(map)) = (highlight, &source_map) {
+        print_highlighted(&output, map);
     } else {
         // Comportement historique : impression sur stdout
-        println!("{}", output);
+        println!("{}", output.as_str());
     }
 
+    // vars_map et output sont effacés en sortant de la portée
     Ok(())
 }
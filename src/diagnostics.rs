@@ -0,0 +1,420 @@
+//! Variable diagnostics: missing / unused variables with "did you mean" hints.
+//!
+//! Front-ends call [`check_variables`](crate::diagnostics::check_variables)
+//! before rendering to report every problem at once, instead of stopping on
+//! the first [`FillError::MissingVariable`](crate::FillError::MissingVariable).
+//!
+//! # Examples
+//!
+//! ```
+//! use std::collections::HashMap;
+//! use fill_prompt::{diagnostics::check_variables, Template};
+//!
+//! let tpl = Template::parse("Crate {{crate_nam}}").unwrap();
+//! let mut vars = HashMap::new();
+//! vars.insert("crate_name".to_string(), "fill_prompt".to_string());
+//!
+//! let check = check_variables(&tpl, &vars).unwrap();
+//! assert_eq!(check.missing[0].name, "crate_nam");
+//! assert_eq!(check.missing[0].did_you_mean.as_deref(), Some("crate_name"));
+//! assert_eq!(check.typos()[0].provided, "crate_name");
+//! ```
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Range;
+
+use crate::{FillError, RenderReport, Template, ValuePolicy};
+
+/// A problematic variable name with the closest known alternative, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    /// The missing placeholder or unused variable.
+    pub name: String,
+    /// Closest provided key (for a missing placeholder) or closest template
+    /// key (for an unused variable).
+    pub did_you_mean: Option<String>,
+}
+
+/// A missing placeholder paired with the unused variable that was most likely
+/// meant for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Typo {
+    /// Key used in the template.
+    pub placeholder: String,
+    /// Key provided by the caller.
+    pub provided: String,
+    /// Edit distance between the two.
+    pub distance: usize,
+}
+
+/// Result of [`check_variables`]. Both lists are sorted by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VariableCheck {
+    /// Placeholders without a value nor a default.
+    pub missing: Vec<Suggestion>,
+    /// Provided variables the template never refers to.
+    pub unused: Vec<Suggestion>,
+}
+
+impl VariableCheck {
+    /// `true` when nothing is missing or unused.
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.unused.is_empty()
+    }
+
+    /// Pair missing placeholders with unused variables, closest pairs first;
+    /// each name appears in at most one pair.
+    pub fn typos(&self) -> Vec<Typo> {
+        let missing: Vec<&str> = self.missing.iter().map(|s| s.name.as_str()).collect();
+        let unused: Vec<&str> = self.unused.iter().map(|s| s.name.as_str()).collect();
+        pair_typos(&missing, &unused)
+    }
+}
+
+/// Compare the keys of `template` with the provided `vars`.
+///
+/// With [`FillOptions::expand_values`](crate::FillOptions::expand_values) and
+/// the default [`ValuePolicy::Allow`], keys
+/// referenced from inside variable values count as well; the other policies
+/// never expand values.
+///
+/// # Errors
+///
+/// [`FillError::NormalizedKeyCollision`] when two names are the same once
+/// normalised, as [`Template::render`] would.
+pub fn check_variables(
+    template: &Template,
+    vars: &HashMap<String, String>,
+) -> Result<VariableCheck, FillError> {
+    let vars = template.options().normalize.apply_vars(vars)?;
+    let vars = vars.as_ref();
+    let (report, missing) = usage(template, vars);
+    let provided: BTreeSet<&str> = vars.keys().map(String::as_str).collect();
+    // keys of the template without a value, defaulted or not
+    let unfilled: BTreeSet<&str> = report
+        .defaulted
+        .iter()
+        .chain(&missing)
+        .map(String::as_str)
+        .collect();
+
+    let missing = missing
+        .iter()
+        .map(|name| Suggestion {
+            name: name.to_string(),
+            did_you_mean: suggest(name, provided.iter().copied()).map(str::to_string),
+        })
+        .collect();
+    let unused = report
+        .unused
+        .iter()
+        .map(|name| Suggestion {
+            name: name.to_string(),
+            did_you_mean: suggest(name, unfilled.iter().copied()).map(str::to_string),
+        })
+        .collect();
+    Ok(VariableCheck { missing, unused })
+}
+
+/// How `template` uses `vars` (already normalised): the [`RenderReport`] of
+/// a successful render, and the keys that have neither a value nor a default.
+pub(crate) fn usage(
+    template: &Template,
+    vars: &HashMap<String, String>,
+) -> (RenderReport, BTreeSet<String>) {
+    let mut keys: BTreeSet<String> = template.keys().into_iter().map(str::to_string).collect();
+    let mut required: BTreeSet<String> = template
+        .required_keys()
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    let options = template.options();
+    if options.expand_values && options.value_policy == ValuePolicy::Allow {
+        let mut pending: Vec<String> = keys.iter().cloned().collect();
+        while let Some(key) = pending.pop() {
+            let Some(value) = vars.get(&key).filter(|v| v.contains("{{")) else {
+                continue;
+            };
+            // malformed values are reported by the renderer itself
+            let Ok(inner) = Template::parse_with(value, template.options()) else {
+                continue;
+            };
+            for p in inner.placeholders() {
+                let provided = vars.contains_key(p.key());
+                if !provided && p.default_value().is_none() {
+                    required.insert(p.key().to_string());
+                }
+                if keys.insert(p.key().to_string()) && provided {
+                    pending.push(p.key().to_string());
+                }
+            }
+        }
+    }
+    let mut report = RenderReport::default();
+    let mut missing = BTreeSet::new();
+    for key in keys {
+        if vars.contains_key(&key) {
+            report.used.insert(key);
+        } else if required.contains(&key) {
+            missing.insert(key);
+        } else {
+            report.defaulted.insert(key);
+        }
+    }
+    report.unused = vars
+        .keys()
+        .filter(|k| !report.used.contains(k.as_str()))
+        .cloned()
+        .collect();
+    (report, missing)
+}
+
+/// Kind of problem found by [`lint_template`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LintKind {
+    /// A placeholder without `fence(...)` sits inside a code fence written in
+    /// the template: a value containing backticks can close the fence early.
+    PlaceholderInFence,
+}
+
+/// A template-level warning; rendering still works.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    /// What was found.
+    pub kind: LintKind,
+    /// Key of the placeholder concerned.
+    pub key: String,
+    /// Byte range of the placeholder in the template.
+    pub span: Range<usize>,
+    /// 1-based line of the placeholder.
+    pub line: usize,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            LintKind::PlaceholderInFence => write!(
+                f,
+                "placeholder `{}` (line {}) sits inside a code fence; a value containing ``` \
+                 would close it, prefer `{{{{{} | fence}}}}` outside the fence",
+                self.key, self.line, self.key
+            ),
+        }
+    }
+}
+
+/// Look for risky constructs in `template`.
+///
+/// # Examples
+///
+/// ```
+/// use fill_prompt::{diagnostics::lint_template, Template};
+///
+/// let tpl = Template::parse("```\n{{code}}\n```\n{{code | fence}}").unwrap();
+/// let lints = lint_template(&tpl);
+/// assert_eq!(lints.len(), 1);
+/// assert_eq!(lints[0].line, 2);
+/// ```
+pub fn lint_template(template: &Template) -> Vec<Lint> {
+    let source = template.source();
+    let fences = fenced_ranges(source);
+    template
+        .placeholders()
+        .filter(|p| p.fence().is_none())
+        .filter(|p| fences.iter().any(|r| r.contains(&p.span().start)))
+        .map(|p| Lint {
+            kind: LintKind::PlaceholderInFence,
+            key: p.key().to_string(),
+            span: p.span(),
+            line: source[..p.span().start].matches('\n').count() + 1,
+        })
+        .collect()
+}
+
+/// Byte ranges covered by the content of Markdown code fences (``` or ~~~,
+/// indented by at most three spaces). An unclosed fence runs to the end.
+fn fenced_ranges(source: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    // (fence char, fence length, content start)
+    let mut open: Option<(char, usize, usize)> = None;
+    let mut offset = 0;
+    for line in source.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        if indent > 3 {
+            continue;
+        }
+        let body = &line[indent..];
+        let Some(c) = body.chars().next().filter(|c| matches!(c, '`' | '~')) else {
+            continue;
+        };
+        let run = body.len() - body.trim_start_matches(c).len();
+        if run < 3 {
+            continue;
+        }
+        match open {
+            None => open = Some((c, run, offset)),
+            Some((oc, olen, start)) if oc == c && run >= olen && body[run..].trim().is_empty() => {
+                ranges.push(start..line_start);
+                open = None;
+            }
+            Some(_) => {}
+        }
+    }
+    if let Some((_, _, start)) = open {
+        ranges.push(start..source.len());
+    }
+    ranges
+}
+
+/// Levenshtein distance between `a` and `b`, counted in chars.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        cur[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+/// Whether two names are close enough to be a typo of each other: at most one
+/// edit per three chars (at least one), and not entirely different.
+fn is_close(a: &str, b: &str, distance: usize) -> bool {
+    let longest = a.chars().count().max(b.chars().count());
+    distance > 0 && distance < longest && distance <= (longest / 3).max(1)
+}
+
+/// The candidate closest to `name`, if it is close enough to be a typo.
+/// Ties go to the first candidate in iteration order.
+pub fn suggest<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    candidates
+        .into_iter()
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|(d, c)| is_close(name, c, *d))
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+/// Greedily pair `missing` and `unused` names, closest pairs first.
+pub fn pair_typos(missing: &[&str], unused: &[&str]) -> Vec<Typo> {
+    let mut candidates: Vec<(usize, &str, &str)> = missing
+        .iter()
+        .flat_map(|m| unused.iter().map(move |u| (edit_distance(m, u), *m, *u)))
+        .filter(|(d, m, u)| is_close(m, u, *d))
+        .collect();
+    candidates.sort();
+
+    let mut taken_missing = BTreeSet::new();
+    let mut taken_unused = BTreeSet::new();
+    let mut typos = Vec::new();
+    for (distance, m, u) in candidates {
+        if taken_missing.contains(m) || taken_unused.contains(u) {
+            continue;
+        }
+        taken_missing.insert(m);
+        taken_unused.insert(u);
+        typos.push(Typo {
+            placeholder: m.to_string(),
+            provided: u.to_string(),
+            distance,
+        });
+    }
+    typos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_basics() {
+        assert_eq!(edit_distance("crate_nam", "crate_name"), 1);
+        assert_eq!(edit_distance("autor", "author"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("développeurs", "developpeurs"), 1);
+    }
+
+    #[test]
+    fn suggest_ignores_unrelated_names() {
+        assert_eq!(
+            suggest("crate_nam", ["author", "crate_name"]),
+            Some("crate_name")
+        );
+        assert_eq!(suggest("msrv", ["license", "author"]), None);
+        assert_eq!(suggest("a", ["b"]), None);
+    }
+
+    #[test]
+    fn typos_are_paired_once() {
+        let typos = pair_typos(
+            &["crate_nam", "autor"],
+            &["crate_name", "author", "licence"],
+        );
+        let pairs: Vec<_> = typos
+            .iter()
+            .map(|t| (t.placeholder.as_str(), t.provided.as_str()))
+            .collect();
+        assert_eq!(pairs, [("autor", "author"), ("crate_nam", "crate_name")]);
+
+        let typos = pair_typos(&["nam", "nme"], &["name"]);
+        assert_eq!(typos.len(), 1);
+    }
+
+    #[test]
+    fn expansion_counts_keys_inside_values() {
+        let options = crate::FillOptions {
+            expand_values: true,
+            ..crate::FillOptions::default()
+        };
+        let tpl = Template::parse_with("{{repository}}", &options).unwrap();
+        let mut vars = HashMap::new();
+        vars.insert(
+            "repository".to_string(),
+            "https://github.com/{{org_name}}/{{crate_name}}".to_string(),
+        );
+        vars.insert("org_name".to_string(), "Max_Perso".to_string());
+        let check = check_variables(&tpl, &vars).unwrap();
+        assert!(check.unused.is_empty());
+        assert_eq!(check.missing[0].name, "crate_name");
+
+        // values are not expanded under the other policies
+        let escaped = crate::FillOptions {
+            value_policy: ValuePolicy::Escape,
+            ..options
+        };
+        let tpl = Template::parse_with("{{repository}}", &escaped).unwrap();
+        let check = check_variables(&tpl, &vars).unwrap();
+        assert!(check.missing.is_empty());
+        assert_eq!(check.unused[0].name, "org_name");
+    }
+
+    #[test]
+    fn lint_flags_placeholders_inside_fences_only() {
+        let tpl =
+            Template::parse("{{a}}\n```rust\n{{b}}\n  ~~~\n{{c | fence}}\n```\n{{d}}\n~~~~\n{{e}}")
+                .unwrap();
+        let keys: Vec<_> = lint_template(&tpl).into_iter().map(|l| l.key).collect();
+        assert_eq!(keys, ["b", "e"]);
+    }
+
+    #[test]
+    fn unused_variable_suggests_template_key() {
+        let tpl = Template::parse(r#"{{lang | default("fr")}}"#).unwrap();
+        let mut vars = HashMap::new();
+        vars.insert("lan".to_string(), "en".to_string());
+        let check = check_variables(&tpl, &vars).unwrap();
+        assert!(check.missing.is_empty());
+        assert_eq!(check.unused[0].did_you_mean.as_deref(), Some("lang"));
+    }
+}
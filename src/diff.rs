@@ -0,0 +1,509 @@
+//! Template diffs, classified by their effect on existing vars files.
+//!
+//! [`diff`](crate::diff::diff) compares two versions of a template: which
+//! placeholders were added or removed, whose default appeared, disappeared or
+//! changed, and which lines of text changed. A
+//! [`Change`](crate::diff::Change) is breaking when a vars file that filled
+//! the old template may no longer fill the new one: a new placeholder without
+//! default, or a default removed. Everything else still renders, with a
+//! different output.
+//!
+//! Text changes are found line by line with placeholders reduced to
+//! `{{key}}`, so editing a filter or a default is not also reported as a
+//! text change; lines that only differ by their placeholders are left to the
+//! placeholder changes.
+//!
+//! # Examples
+//!
+//! ```
+//! use fill_prompt::diff::{diff, Change};
+//! use fill_prompt::Template;
+//!
+//! let old = Template::parse("Hi {{name}} ({{lang | default(\"fr\")}})").unwrap();
+//! let new = Template::parse("Hello {{name}}, {{tone}} ({{lang}})").unwrap();
+//! let changes = diff(&old, &new);
+//! assert!(changes.is_breaking());
+//! assert_eq!(
+//!     changes.changes()[0],
+//!     Change::DefaultRemoved { key: "lang".into(), value: "fr".into() }
+//! );
+//! assert_eq!(changes.changes()[1].to_string(), "added required placeholder `tone`");
+//! assert_eq!(changes.changes()[2].to_string(), "changed text: line 1 -> line 1");
+//! ```
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+use crate::escape::Escape;
+use crate::front_matter::FrontMatter;
+use crate::Template;
+
+/// Cells of the line table compared exactly; bigger edits are reported as
+/// one changed block.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// One difference between two templates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// A new key; `required` when no occurrence has a default (breaking).
+    PlaceholderAdded {
+        /// The key.
+        key: String,
+        /// No default: existing vars files do not provide it.
+        required: bool,
+    },
+    /// A key no longer used; vars files still providing it fail with
+    /// `--strict`.
+    PlaceholderRemoved {
+        /// The key.
+        key: String,
+    },
+    /// A required key got a default.
+    DefaultAdded {
+        /// The key.
+        key: String,
+        /// The new default.
+        value: String,
+    },
+    /// A key lost its default and became required (breaking).
+    DefaultRemoved {
+        /// The key.
+        key: String,
+        /// The old default.
+        value: String,
+    },
+    /// A default changed value.
+    DefaultChanged {
+        /// The key.
+        key: String,
+        /// The old default.
+        old: String,
+        /// The new default.
+        new: String,
+    },
+    /// Lines of text changed; 0-based line ranges, empty for a pure
+    /// insertion or deletion.
+    TextChanged {
+        /// Lines of the old template.
+        old_lines: Range<usize>,
+        /// Lines of the new template.
+        new_lines: Range<usize>,
+    },
+}
+
+impl Change {
+    /// Whether a vars file that filled the old template may not fill the new
+    /// one.
+    pub fn is_breaking(&self) -> bool {
+        matches!(
+            self,
+            Change::PlaceholderAdded { required: true, .. } | Change::DefaultRemoved { .. }
+        )
+    }
+
+    /// Short identifier: `placeholder_added`, `default_removed`...
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Change::PlaceholderAdded { .. } => "placeholder_added",
+            Change::PlaceholderRemoved { .. } => "placeholder_removed",
+            Change::DefaultAdded { .. } => "default_added",
+            Change::DefaultRemoved { .. } => "default_removed",
+            Change::DefaultChanged { .. } => "default_changed",
+            Change::TextChanged { .. } => "text_changed",
+        }
+    }
+}
+
+/// `line 3`, `lines 3-5`, or `no line (after line 2)`.
+fn lines(range: &Range<usize>) -> String {
+    match range.len() {
+        0 => format!("no line (after line {})", range.start),
+        1 => format!("line {}", range.start + 1),
+        _ => format!("lines {}-{}", range.start + 1, range.end),
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::PlaceholderAdded { key, required } => {
+                let kind = if *required { "required" } else { "optional" };
+                write!(f, "added {} placeholder `{}`", kind, key)
+            }
+            Change::PlaceholderRemoved { key } => write!(f, "removed placeholder `{}`", key),
+            Change::DefaultAdded { key, value } => {
+                write!(f, "added default {:?} to `{}`", value, key)
+            }
+            Change::DefaultRemoved { key, value } => {
+                write!(f, "removed default {:?} of `{}`, now required", value, key)
+            }
+            Change::DefaultChanged { key, old, new } => {
+                write!(
+                    f,
+                    "changed default of `{}` from {:?} to {:?}",
+                    key, old, new
+                )
+            }
+            Change::TextChanged {
+                old_lines,
+                new_lines,
+            } => write!(
+                f,
+                "changed text: {} -> {}",
+                lines(old_lines),
+                lines(new_lines)
+            ),
+        }
+    }
+}
+
+/// The changes from one template to another, placeholder changes by key,
+/// then text changes in line order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TemplateDiff {
+    changes: Vec<Change>,
+}
+
+impl TemplateDiff {
+    /// All changes.
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// No change at all.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Whether any change is breaking.
+    pub fn is_breaking(&self) -> bool {
+        self.changes.iter().any(Change::is_breaking)
+    }
+
+    /// The diff as JSON: `{"breaking":bool,"changes":[{"kind":...,
+    /// "breaking":bool,"message":...}]}`, each change with its fields (`key`,
+    /// `required`, `value`, `old`, `new`, or 1-based inclusive `old_lines` /
+    /// `new_lines` pairs, `[n+1,n]` when empty).
+    pub fn to_json(&self) -> String {
+        let string = |out: &mut String, name: &str, value: &str| {
+            out.push_str(&format!(",\"{}\":\"", name));
+            Escape::Json.push(out, value);
+            out.push('"');
+        };
+        let mut out = format!("{{\"breaking\":{},\"changes\":[", self.is_breaking());
+        for (i, change) in self.changes.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(&format!(
+                "{{\"kind\":\"{}\",\"breaking\":{}",
+                change.kind(),
+                change.is_breaking()
+            ));
+            match change {
+                Change::PlaceholderAdded { key, required } => {
+                    string(&mut out, "key", key);
+                    out.push_str(&format!(",\"required\":{}", required));
+                }
+                Change::PlaceholderRemoved { key } => string(&mut out, "key", key),
+                Change::DefaultAdded { key, value } | Change::DefaultRemoved { key, value } => {
+                    string(&mut out, "key", key);
+                    string(&mut out, "value", value);
+                }
+                Change::DefaultChanged { key, old, new } => {
+                    string(&mut out, "key", key);
+                    string(&mut out, "old", old);
+                    string(&mut out, "new", new);
+                }
+                Change::TextChanged {
+                    old_lines,
+                    new_lines,
+                } => out.push_str(&format!(
+                    ",\"old_lines\":[{},{}],\"new_lines\":[{},{}]",
+                    old_lines.start + 1,
+                    old_lines.end,
+                    new_lines.start + 1,
+                    new_lines.end
+                )),
+            }
+            string(&mut out, "message", &change.to_string());
+            out.push('}');
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+/// Compare `old` with `new`.
+pub fn diff(old: &Template, new: &Template) -> TemplateDiff {
+    let none = FrontMatter::default();
+    diff_with_front_matter((old, &none), (new, &none))
+}
+
+/// [`diff`] of templates with a front matter: a default or `required` flag
+/// declared there takes precedence over the placeholders' defaults, as when
+/// rendering.
+pub fn diff_with_front_matter(
+    (old, old_front): (&Template, &FrontMatter),
+    (new, new_front): (&Template, &FrontMatter),
+) -> TemplateDiff {
+    let mut changes = Vec::new();
+    let (old_keys, new_keys) = (defaults(old, old_front), defaults(new, new_front));
+    for (key, old_default) in &old_keys {
+        let key = key.to_string();
+        match (old_default, new_keys.get(key.as_str())) {
+            (_, None) => changes.push(Change::PlaceholderRemoved { key }),
+            (None, Some(Some(value))) => changes.push(Change::DefaultAdded {
+                key,
+                value: value.to_string(),
+            }),
+            (Some(value), Some(None)) => changes.push(Change::DefaultRemoved {
+                key,
+                value: value.to_string(),
+            }),
+            (Some(old), Some(Some(new))) if old != new => changes.push(Change::DefaultChanged {
+                key,
+                old: old.to_string(),
+                new: new.to_string(),
+            }),
+            _ => {}
+        }
+    }
+    for (key, default) in &new_keys {
+        if !old_keys.contains_key(key) {
+            changes.push(Change::PlaceholderAdded {
+                key: key.to_string(),
+                required: default.is_none(),
+            });
+        }
+    }
+    changes.sort_by(|a, b| key_of(a).cmp(key_of(b)));
+    changes.extend(text_changes(old, new));
+    TemplateDiff { changes }
+}
+
+fn key_of(change: &Change) -> &str {
+    match change {
+        Change::PlaceholderAdded { key, .. }
+        | Change::PlaceholderRemoved { key }
+        | Change::DefaultAdded { key, .. }
+        | Change::DefaultRemoved { key, .. }
+        | Change::DefaultChanged { key, .. } => key,
+        Change::TextChanged { .. } => "",
+    }
+}
+
+/// Each key with its default, `None` when some occurrence has none (the
+/// key is then required); otherwise the first default written. The front
+/// matter overrides both.
+fn defaults<'t>(
+    template: &'t Template,
+    front: &'t FrontMatter,
+) -> BTreeMap<&'t str, Option<&'t str>> {
+    let mut keys: BTreeMap<&str, Option<&str>> = BTreeMap::new();
+    for p in template.placeholders() {
+        let default = p.default_value();
+        keys.entry(p.key())
+            .and_modify(|d| *d = d.and(default))
+            .or_insert(default);
+    }
+    for (key, default) in &mut keys {
+        if let Some(spec) = front.variables.get(*key) {
+            if spec.required {
+                *default = None;
+            } else if let Some(value) = &spec.default {
+                *default = Some(value);
+            }
+        }
+    }
+    keys
+}
+
+/// The source with every placeholder written as `{{key}}` (newlines inside
+/// a placeholder kept, so line numbers match) and the same without
+/// placeholders at all.
+fn normalized(template: &Template) -> (String, String) {
+    let source = template.source();
+    let mut spans: Vec<_> = template
+        .placeholders()
+        .map(|p| (p.span(), p.key()))
+        .collect();
+    spans.sort_by_key(|(span, _)| span.start);
+    let (mut keyed, mut bare) = (String::new(), String::new());
+    let mut last = 0;
+    for (span, key) in spans {
+        let newlines = "\n".repeat(source[span.clone()].matches('\n').count());
+        keyed.push_str(&source[last..span.start]);
+        keyed.push_str(&format!("{{{{{}}}}}{}", key, newlines));
+        bare.push_str(&source[last..span.start]);
+        bare.push_str(&newlines);
+        last = span.end;
+    }
+    keyed.push_str(&source[last..]);
+    bare.push_str(&source[last..]);
+    (keyed, bare)
+}
+
+/// Blocks of changed lines whose text, placeholders aside, differs.
+fn text_changes(old: &Template, new: &Template) -> Vec<Change> {
+    let (old_keyed, old_bare) = normalized(old);
+    let (new_keyed, new_bare) = normalized(new);
+    let (a, b): (Vec<&str>, Vec<&str>) = (
+        old_keyed.split('\n').collect(),
+        new_keyed.split('\n').collect(),
+    );
+    let (a_bare, b_bare): (Vec<&str>, Vec<&str>) = (
+        old_bare.split('\n').collect(),
+        new_bare.split('\n').collect(),
+    );
+    changed_blocks(&a, &b)
+        .into_iter()
+        .filter(|(x, y)| {
+            text_lines(&a[x.clone()], &a_bare[x.clone()])
+                != text_lines(&b[y.clone()], &b_bare[y.clone()])
+        })
+        .map(|(old_lines, new_lines)| Change::TextChanged {
+            old_lines,
+            new_lines,
+        })
+        .collect()
+}
+
+/// The text of `bare` lines, without the lines that only held placeholders.
+fn text_lines<'s>(keyed: &[&str], bare: &[&'s str]) -> Vec<&'s str> {
+    keyed
+        .iter()
+        .zip(bare)
+        .filter(|(k, b)| k == b || !b.trim().is_empty())
+        .map(|(_, b)| *b)
+        .collect()
+}
+
+/// Maximal runs of lines outside a longest common subsequence of `a` and
+/// `b`, as pairs of ranges.
+fn changed_blocks(a: &[&str], b: &[&str]) -> Vec<(Range<usize>, Range<usize>)> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    if a_mid.is_empty() && b_mid.is_empty() {
+        return Vec::new();
+    }
+    let whole = vec![(prefix..prefix + a_mid.len(), prefix..prefix + b_mid.len())];
+    if a_mid.is_empty() || b_mid.is_empty() || a_mid.len() * b_mid.len() > MAX_DIFF_CELLS {
+        return whole;
+    }
+
+    // lcs[i][j]: length of the LCS of a_mid[i..] and b_mid[j..]
+    let (n, m) = (a_mid.len(), b_mid.len());
+    let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i * (m + 1) + j] = if a_mid[i] == b_mid[j] {
+                lcs[(i + 1) * (m + 1) + j + 1] + 1
+            } else {
+                lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+            };
+        }
+    }
+    let mut blocks = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let (mut start_i, mut start_j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && a_mid[i] == b_mid[j] {
+            if (start_i, start_j) != (i, j) {
+                blocks.push((prefix + start_i..prefix + i, prefix + start_j..prefix + j));
+            }
+            i += 1;
+            j += 1;
+            (start_i, start_j) = (i, j);
+        } else if j < m && (i == n || lcs[i * (m + 1) + j + 1] >= lcs[(i + 1) * (m + 1) + j]) {
+            j += 1;
+        } else {
+            i += 1;
+        }
+    }
+    if (start_i, start_j) != (n, m) {
+        blocks.push((prefix + start_i..prefix + n, prefix + start_j..prefix + m));
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(source: &str) -> Template {
+        Template::parse(source).unwrap()
+    }
+
+    #[test]
+    fn classifies_placeholder_and_default_changes() {
+        let old = parsed("{{a}} {{b | default(\"x\")}} {{c}} {{d | default(\"1\")}} {{gone}}");
+        let new = parsed("{{a | json}} {{b}} {{c | default(\"y\")}} {{d | default(\"2\")}} {{e | default(\"z\")}}");
+        let changes = diff(&old, &new);
+        let summary: Vec<(&str, bool)> = changes
+            .changes()
+            .iter()
+            .map(|c| (c.kind(), c.is_breaking()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("default_removed", true),
+                ("default_added", false),
+                ("default_changed", false),
+                ("placeholder_added", false),
+                ("placeholder_removed", false),
+            ]
+        );
+        assert!(changes.is_breaking());
+        assert!(diff(&old, &old).is_empty());
+
+        let mut front = FrontMatter::default();
+        front.variables.insert(
+            "a".into(),
+            crate::front_matter::VarSpec {
+                default: Some("fm".into()),
+                ..Default::default()
+            },
+        );
+        let with_default = diff_with_front_matter((&old, &FrontMatter::default()), (&old, &front));
+        assert_eq!(
+            with_default.changes(),
+            [Change::DefaultAdded {
+                key: "a".into(),
+                value: "fm".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn text_changes_ignore_placeholder_edits() {
+        let old = parsed("Title\n{{a}}\nkeep\nold line\nend");
+        let new = parsed("Title\n{{a | json}}\nkeep\nnew line\nadded\nend\n{{b}}");
+        let changes = diff(&old, &new);
+        let text: Vec<&Change> = changes
+            .changes()
+            .iter()
+            .filter(|c| c.kind() == "text_changed")
+            .collect();
+        assert_eq!(
+            text,
+            [&Change::TextChanged {
+                old_lines: 3..4,
+                new_lines: 3..5,
+            }]
+        );
+        assert_eq!(text[0].to_string(), "changed text: line 4 -> lines 4-5");
+        // the new `{{b}}` line is only a placeholder change
+        assert_eq!(changes.changes().len(), 2);
+        assert!(changes.to_json().starts_with(
+            r#"{"breaking":true,"changes":[{"kind":"placeholder_added","breaking":true,"key":"b","required":true,"message":"added required placeholder `b`"},"#
+        ));
+    }
+}
@@ -0,0 +1,332 @@
+//! Output escaping for the format a template is written in.
+//!
+//! An [`Escape`] mode is chosen engine-wide with
+//! [`FillOptions::escape`](crate::FillOptions::escape) (see
+//! [`Escape::from_path`] for `.json.tpl`, `.yaml.tpl`...) or per placeholder
+//! with a filter: `{{x | json}}`, `{{x | yaml}}`, `{{x | shell}}`,
+//! `{{x | xml}}`, `{{x | markdown}}`, or `{{x | raw}}` to opt out.
+//!
+//! # Examples
+//!
+//! ```
+//! use fill_prompt::escape::Escape;
+//!
+//! assert_eq!(Escape::Json.apply("say \"hi\"\n"), r#"say \"hi\"\n"#);
+//! assert_eq!(Escape::Shell.apply("it's"), r#"'it'\''s'"#);
+//! assert_eq!(Escape::from_path("templates/request.json.tpl"), Escape::Json);
+//! ```
+use std::borrow::Cow;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// How a value is escaped before being inserted into the output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Escape {
+    /// Insert the value as it is.
+    #[default]
+    None,
+    /// Content of a JSON string (the template supplies the quotes):
+    /// `"`, `\` and control characters are backslash-escaped.
+    Json,
+    /// A complete double-quoted YAML scalar, quotes included.
+    Yaml,
+    /// A single POSIX shell word, single-quoted (`'` becomes `'\''`).
+    Shell,
+    /// XML/HTML text or attribute content: `& < > " '` become entities.
+    Xml,
+    /// Markdown inline text: punctuation that could start markup is
+    /// backslash-escaped and line breaks become spaces.
+    Markdown,
+}
+
+impl Escape {
+    /// The mode named by a filter (`json`, `yaml`, `shell`, `xml`,
+    /// `markdown`/`md`, `raw`).
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "raw" => Some(Escape::None),
+            "json" => Some(Escape::Json),
+            "yaml" | "yml" => Some(Escape::Yaml),
+            "shell" | "sh" => Some(Escape::Shell),
+            "xml" | "html" => Some(Escape::Xml),
+            "markdown" | "md" => Some(Escape::Markdown),
+            _ => None,
+        }
+    }
+
+    /// Pick the mode from the extension preceding `.tpl` (e.g.
+    /// `body.json.tpl`). Any other path, `prompt.md` or `run.sh` included,
+    /// maps to [`Escape::None`]: a single extension names the kind of
+    /// template, not how its values must be escaped.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        let ext = |p: &Path| {
+            p.extension()
+                .and_then(|e| e.to_str())
+                .map(str::to_ascii_lowercase)
+        };
+        match ext(path) {
+            Some(e) if e == "tpl" => path
+                .file_stem()
+                .and_then(|s| ext(Path::new(s)))
+                .and_then(|e| Escape::from_name(&e))
+                .unwrap_or_default(),
+            _ => Escape::None,
+        }
+    }
+
+    /// Escape `value`, borrowing it when nothing needs to change.
+    pub fn apply(self, value: &str) -> Cow<'_, str> {
+        if self == Escape::None {
+            return Cow::Borrowed(value);
+        }
+        let mut out = String::with_capacity(value.len() + 2);
+        self.push(&mut out, value);
+        Cow::Owned(out)
+    }
+
+    /// Append the escaped `value` to `out`.
+    pub fn push(self, out: &mut String, value: &str) {
+        match self {
+            Escape::None => out.push_str(value),
+            Escape::Json => push_json(out, value),
+            Escape::Yaml => {
+                // YAML double-quoted scalars accept the JSON escapes
+                out.push('"');
+                push_json(out, value);
+                out.push('"');
+            }
+            Escape::Shell => {
+                out.push('\'');
+                out.push_str(&value.replace('\'', r"'\''"));
+                out.push('\'');
+            }
+            Escape::Xml => {
+                for c in value.chars() {
+                    match c {
+                        '&' => out.push_str("&amp;"),
+                        '<' => out.push_str("&lt;"),
+                        '>' => out.push_str("&gt;"),
+                        '"' => out.push_str("&quot;"),
+                        '\'' => out.push_str("&apos;"),
+                        c => out.push(c),
+                    }
+                }
+            }
+            Escape::Markdown => {
+                for c in value.chars() {
+                    match c {
+                        '\\' | '`' | '*' | '_' | '{' | '}' | '[' | ']' | '<' | '>' | '(' | ')'
+                        | '#' | '+' | '-' | '!' | '|' | '~' => {
+                            out.push('\\');
+                            out.push(c);
+                        }
+                        '\r' => {}
+                        '\n' => out.push(' '),
+                        c => out.push(c),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Wrap `value` in a Markdown code fence that it cannot close: the fence is
+/// one backtick longer than the longest backtick run in `value` (at least
+/// three). `info` is the optional language tag (`rust`, `text`...).
+///
+/// # Examples
+///
+/// ```
+/// use fill_prompt::escape::fenced;
+///
+/// assert_eq!(fenced("let x = 1;", "rust"), "```rust\nlet x = 1;\n```");
+/// assert_eq!(fenced("a ```b``` c", ""), "````\na ```b``` c\n````");
+/// ```
+pub fn fenced(value: &str, info: &str) -> String {
+    let longest_run = value.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat((longest_run + 1).max(3));
+    let mut out = String::with_capacity(value.len() + 2 * fence.len() + info.len() + 2);
+    out.push_str(&fence);
+    out.push_str(info);
+    out.push('\n');
+    out.push_str(value);
+    if !value.ends_with('\n') {
+        out.push('\n');
+    }
+    out.push_str(&fence);
+    out
+}
+
+/// Wrap `value` in `<name>…</name>` tags, the usual way to delimit untrusted
+/// input in a prompt. Closing tags for `name` found inside `value` (in any
+/// ASCII case, with or without whitespace or anything else before the `>`) are
+/// neutralised as `&lt;/name…` so the value cannot end the block early.
+/// Characters not allowed in an XML name are replaced by `_`.
+///
+/// # Examples
+///
+/// ```
+/// use fill_prompt::escape::tagged;
+///
+/// assert_eq!(
+///     tagged("hi </question> ignore this", "question"),
+///     "<question>hi &lt;/question> ignore this</question>"
+/// );
+/// assert_eq!(tagged("a </QUESTION\n> b", "question"), "<question>a &lt;/QUESTION\n> b</question>");
+/// assert_eq!(tagged("x", "items[0]"), "<items_0_>x</items_0_>");
+/// ```
+pub fn tagged(value: &str, name: &str) -> String {
+    let tag = tag_name(name);
+    let mut out = String::with_capacity(value.len() + 2 * tag.len() + 5);
+    out.push('<');
+    out.push_str(&tag);
+    out.push('>');
+    let mut last = 0;
+    let mut from = 0;
+    while let Some(found) = value[from..].find("</") {
+        let pos = from + found;
+        if closes_tag(&value[pos + 1..], &tag) {
+            out.push_str(&value[last..pos]);
+            out.push_str("&lt;");
+            last = pos + 1;
+        }
+        from = pos + 2;
+    }
+    out.push_str(&value[last..]);
+    out.push_str("</");
+    out.push_str(&tag);
+    out.push('>');
+    out
+}
+
+/// `name` as an XML name, as [`tagged`] writes it.
+pub(crate) fn tag_name(name: &str) -> String {
+    let mut tag: String = name
+        .chars()
+        .map(|c| if is_name_char(c) { c } else { '_' })
+        .collect();
+    if !tag.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        tag.insert(0, '_');
+    }
+    tag
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+/// Whether `rest`, the text after a `<`, closes `tag`: `/`, optional
+/// whitespace, then `tag` in any ASCII case, not followed by another name
+/// character. Parsers and models read `</tag >` or `</tag\n>` as closing
+/// tags too.
+pub(crate) fn closes_tag(rest: &str, tag: &str) -> bool {
+    // compare bytes in place: lowercasing the value could move them
+    let Some(rest) = rest.strip_prefix('/') else {
+        return false;
+    };
+    let rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace());
+    rest.as_bytes()
+        .get(..tag.len())
+        .is_some_and(|name| name.eq_ignore_ascii_case(tag.as_bytes()))
+        && !rest[tag.len()..].starts_with(is_name_char)
+}
+
+fn push_json(out: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                // writing to a String cannot fail
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_and_yaml() {
+        assert_eq!(
+            Escape::Json.apply("a\"b\\c\n\u{1}"),
+            "a\\\"b\\\\c\\n\\u0001"
+        );
+        assert_eq!(
+            Escape::Yaml.apply("key: value\nnext"),
+            "\"key: value\\nnext\""
+        );
+    }
+
+    #[test]
+    fn shell_xml_markdown() {
+        assert_eq!(
+            Escape::Shell.apply("rm -rf $HOME; echo 'x'"),
+            r#"'rm -rf $HOME; echo '\''x'\'''"#
+        );
+        assert_eq!(
+            Escape::Xml.apply("<a href=\"x\">&</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+        assert_eq!(
+            Escape::Markdown.apply("**gras** [lien](x)\n# titre"),
+            r"\*\*gras\*\* \[lien\]\(x\) \# titre"
+        );
+    }
+
+    #[test]
+    fn fence_outgrows_inner_backticks() {
+        assert_eq!(fenced("x\n", "text"), "```text\nx\n```");
+        assert_eq!(
+            fenced("```rust\nfn main() {}\n```\n", "markdown"),
+            "````markdown\n```rust\nfn main() {}\n```\n````"
+        );
+        assert_eq!(fenced("``````", ""), "```````\n``````\n```````");
+    }
+
+    #[test]
+    fn tagged_neutralises_closing_tag_in_any_case() {
+        assert_eq!(
+            tagged("a</CTX>b</ctx>", "ctx"),
+            "<ctx>a&lt;/CTX>b&lt;/ctx></ctx>"
+        );
+        // whitespace or attributes before `>`, whitespace after `</`
+        assert_eq!(
+            tagged("evil </x > </x\n> </ X> </x y> done", "x"),
+            "<x>evil &lt;/x > &lt;/x\n> &lt;/ X> &lt;/x y> done</x>"
+        );
+        // another tag, or a longer name, is left alone
+        assert_eq!(
+            tagged("</xy> </x-y> </y> a</x", "x"),
+            "<x></xy> </x-y> </y> a&lt;/x</x>"
+        );
+        assert_eq!(tagged("", "1st"), "<_1st></_1st>");
+    }
+
+    #[test]
+    fn tagged_keeps_offsets_when_lowercasing_would_move_bytes() {
+        // Ω (U+2126) lowercases one byte shorter, İ one byte longer
+        assert_eq!(tagged("Ω</ctx>İ", "ctx"), "<ctx>Ω&lt;/ctx>İ</ctx>");
+        assert_eq!(tagged("İ</Ctx>Ω</c", "ctx"), "<ctx>İ&lt;/Ctx>Ω</c</ctx>");
+    }
+
+    #[test]
+    fn mode_from_path() {
+        assert_eq!(Escape::from_path("a/body.json.tpl"), Escape::Json);
+        assert_eq!(Escape::from_path("config.YAML.tpl"), Escape::Yaml);
+        assert_eq!(Escape::from_path("run.sh.tpl"), Escape::Shell);
+        // only the double extension counts
+        assert_eq!(Escape::from_path("run.sh"), Escape::None);
+        assert_eq!(Escape::from_path("prompt.md"), Escape::None);
+        assert_eq!(Escape::from_path("page.html"), Escape::None);
+        assert_eq!(Escape::from_path("templates/gen_test_1.tpl"), Escape::None);
+        assert_eq!(Escape::from_path("notes.txt"), Escape::None);
+    }
+}
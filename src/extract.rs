@@ -0,0 +1,482 @@
+//! Reverse extraction: recover the variables of an output rendered from a
+//! known template (e.g. a prompt found in logs).
+//!
+//! [`Template::extract`](crate::Template::extract) matches the literal text of
+//! the template against the output and captures what sits between as the
+//! placeholder values. Tags, fences and indentation added by the renderer are
+//! removed again; escaped values cannot be told apart from their escaping and
+//! are refused ([`ExtractError::Escaped`](crate::extract::ExtractError::Escaped)).
+//! The result is checked by rendering the template with it.
+//!
+//! The split must be unique: adjacent placeholders (`{{a}}{{b}}`), or a value
+//! that may contain the literal following it, usually leave several
+//! candidates and give [`ExtractError::Ambiguous`](crate::extract::ExtractError::Ambiguous),
+//! unless a repeated key settles it. A key used several times must have the
+//! same value everywhere. A placeholder with a default is extracted like the
+//! others, since the output does not say whether the default was used.
+//!
+//! # Examples
+//!
+//! ```
+//! use fill_prompt::Template;
+//!
+//! let tpl = Template::parse("Translate {{text}} into {{lang}}.").unwrap();
+//! let vars = tpl.extract("Translate bonjour into English.").unwrap();
+//! assert_eq!(vars["text"], "bonjour");
+//! assert_eq!(vars["lang"], "English");
+//! ```
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::escape::{closes_tag, tag_name, Escape};
+use crate::template::{continuation_prefix, Placeholder};
+use crate::Template;
+
+/// Matching work allowed before giving up, which bounds the backtracking on
+/// outputs where the literals occur many times: one step per piece matched
+/// and per candidate end tried, plus one per 64 bytes copied to undo
+/// decorations. Every step costs at most a binary search or a short copy.
+const MAX_STEPS: usize = 1_000_000;
+
+/// Why variables could not be extracted. Errors never contain values.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ExtractError {
+    /// Partials, parents and blocks need a [`TemplateSet`](crate::registry::TemplateSet)
+    /// to render and are not supported.
+    #[error("cannot extract through section `{0}`")]
+    Section(String),
+
+    /// The value of this placeholder is escaped.
+    #[error("cannot extract `{0}`: its value is escaped")]
+    Escaped(String),
+
+    /// The output does not follow the template; carries the furthest byte
+    /// index that could be matched.
+    #[error("rendered text does not match the template at byte index {0}")]
+    NoMatch(usize),
+
+    /// Several splits fit the output; carries the keys they disagree on.
+    #[error("ambiguous split: several values fit {}", .0.join(", "))]
+    Ambiguous(Vec<String>),
+
+    /// The output only matches with different values for this repeated key.
+    #[error("`{0}` appears with different values")]
+    Inconsistent(String),
+
+    /// Too many ways to match the literals were tried.
+    #[error("too many candidate splits, giving up")]
+    TooComplex,
+}
+
+/// A literal or a placeholder of a template without sections.
+pub(crate) enum Piece<'t> {
+    Literal(&'t str),
+    Value(&'t Placeholder),
+}
+
+/// Match `pieces` of `template` against `rendered`.
+pub(crate) fn extract(
+    template: &Template,
+    pieces: &[Piece<'_>],
+    rendered: &str,
+) -> Result<HashMap<String, String>, ExtractError> {
+    for piece in pieces {
+        if let Piece::Value(p) = piece {
+            if p.decorations(template.options()).escape != Escape::None {
+                return Err(ExtractError::Escaped(p.key().to_string()));
+            }
+        }
+    }
+    let occurrences = occurrences(pieces, rendered);
+    let mut search = Search::new(template, pieces, &occurrences, rendered, true);
+    search.run(0, 0)?;
+    let vars = match search.solutions.len() {
+        1 => search.solutions.remove(0),
+        0 => {
+            // same search with each occurrence on its own, to tell a
+            // repeated key that disagrees from a plain mismatch
+            let furthest = search.furthest;
+            let mut relaxed = Search::new(template, pieces, &occurrences, rendered, false);
+            relaxed.run(0, 0)?;
+            return Err(match relaxed.conflict {
+                Some(key) => ExtractError::Inconsistent(key),
+                None => ExtractError::NoMatch(furthest),
+            });
+        }
+        _ => {
+            let (a, b) = (&search.solutions[0], &search.solutions[1]);
+            let mut keys: Vec<String> = a
+                .iter()
+                .filter(|(k, v)| b.get(*k) != Some(v))
+                .map(|(k, _)| k.clone())
+                .collect();
+            keys.sort();
+            return Err(ExtractError::Ambiguous(keys));
+        }
+    };
+    match template.render_lookup(&vars) {
+        Ok(output) if output == rendered => Ok(vars),
+        Ok(output) => {
+            let at = output
+                .bytes()
+                .zip(rendered.bytes())
+                .take_while(|(a, b)| a == b)
+                .count();
+            Err(ExtractError::NoMatch(at))
+        }
+        Err(_) => Err(ExtractError::NoMatch(0)),
+    }
+}
+
+/// Byte indices of every occurrence (overlapping ones too) of each literal
+/// piece in `rendered`, empty for values: found once, then looked up by
+/// binary search whatever the number of candidate splits.
+fn occurrences(pieces: &[Piece<'_>], rendered: &str) -> Vec<Vec<usize>> {
+    pieces
+        .iter()
+        .map(|piece| {
+            let Piece::Literal(text) = piece else {
+                return Vec::new();
+            };
+            let mut found = Vec::new();
+            let mut from = 0;
+            while let Some(at) = rendered[from..].find(text) {
+                found.push(from + at);
+                from += at
+                    + rendered[from + at..]
+                        .chars()
+                        .next()
+                        .map_or(1, char::len_utf8);
+                if from > rendered.len() {
+                    break;
+                }
+            }
+            found
+        })
+        .collect()
+}
+
+/// Candidate ends of a value, in increasing order, produced one at a time.
+enum Ends<'a> {
+    /// The value runs to the end of the output.
+    Last(Option<usize>),
+    /// Where the following literal occurs.
+    Literal(std::slice::Iter<'a, usize>),
+    /// Every char boundary from `from`: the value is followed by another.
+    Chars { rendered: &'a str, from: usize },
+}
+
+impl Iterator for Ends<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        match self {
+            Ends::Last(end) => end.take(),
+            Ends::Literal(at) => at.next().copied(),
+            Ends::Chars { rendered, from } => {
+                let at = *from;
+                if at > rendered.len() {
+                    return None;
+                }
+                *from = at + rendered[at..].chars().next().map_or(1, char::len_utf8);
+                Some(at)
+            }
+        }
+    }
+}
+
+/// Backtracking over the possible ends of each value, collecting up to two
+/// distinct solutions.
+struct Search<'a> {
+    template: &'a Template,
+    pieces: &'a [Piece<'a>],
+    /// [`occurrences`] of the literal pieces.
+    occurrences: &'a [Vec<usize>],
+    rendered: &'a str,
+    /// Require a repeated key to have one value; when off, the first key
+    /// seen with two values is recorded in `conflict`.
+    consistent: bool,
+    bindings: Vec<(&'a str, Cow<'a, str>)>,
+    solutions: Vec<HashMap<String, String>>,
+    conflict: Option<String>,
+    furthest: usize,
+    steps: usize,
+}
+
+impl<'a> Search<'a> {
+    fn new(
+        template: &'a Template,
+        pieces: &'a [Piece<'a>],
+        occurrences: &'a [Vec<usize>],
+        rendered: &'a str,
+        consistent: bool,
+    ) -> Self {
+        Search {
+            template,
+            pieces,
+            occurrences,
+            rendered,
+            consistent,
+            bindings: Vec::new(),
+            solutions: Vec::new(),
+            conflict: None,
+            furthest: 0,
+            steps: 0,
+        }
+    }
+
+    /// Enough found: two solutions, or any one when relaxed.
+    fn done(&self) -> bool {
+        self.solutions.len() >= if self.consistent { 2 } else { 1 }
+    }
+
+    /// Count `steps` against [`MAX_STEPS`].
+    fn charge(&mut self, steps: usize) -> Result<(), ExtractError> {
+        self.steps += steps;
+        if self.steps > MAX_STEPS {
+            return Err(ExtractError::TooComplex);
+        }
+        Ok(())
+    }
+
+    fn run(&mut self, i: usize, pos: usize) -> Result<(), ExtractError> {
+        self.charge(1)?;
+        self.furthest = self.furthest.max(pos);
+        let Some(piece) = self.pieces.get(i) else {
+            if pos == self.rendered.len() {
+                self.record();
+            }
+            return Ok(());
+        };
+        let p = match piece {
+            Piece::Literal(text) => {
+                if self.rendered[pos..].starts_with(text) {
+                    self.run(i + 1, pos + text.len())?;
+                }
+                return Ok(());
+            }
+            Piece::Value(p) => *p,
+        };
+        for end in self.ends(i + 1, pos) {
+            self.charge(1)?;
+            let value = self.decode(p, pos..end);
+            if let Some(Cow::Owned(value)) = &value {
+                self.charge(value.len() / 64)?;
+            }
+            let Some(value) = value else {
+                continue;
+            };
+            let bound = self
+                .bindings
+                .iter()
+                .find(|(k, _)| *k == p.key())
+                .map(|(_, v)| v != &value);
+            if self.consistent && bound == Some(true) {
+                continue;
+            }
+            self.bindings.push((p.key(), value));
+            self.run(i + 1, end)?;
+            self.bindings.pop();
+            if self.done() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Candidate ends for a value starting at `pos`, followed by piece
+    /// `next`.
+    fn ends(&self, next: usize, pos: usize) -> Ends<'a> {
+        match self.pieces.get(next) {
+            None => Ends::Last(Some(self.rendered.len())),
+            Some(Piece::Literal(_)) => {
+                let found = &self.occurrences[next];
+                Ends::Literal(found[found.partition_point(|&at| at < pos)..].iter())
+            }
+            Some(Piece::Value(_)) => Ends::Chars {
+                rendered: self.rendered,
+                from: pos,
+            },
+        }
+    }
+
+    /// The value of `p` rendered at `range`, with its decorations removed;
+    /// `None` when they are not there.
+    fn decode(&self, p: &Placeholder, range: std::ops::Range<usize>) -> Option<Cow<'a, str>> {
+        let decorations = p.decorations(self.template.options());
+        let rendered: &'a str = self.rendered;
+        let mut text = Cow::Borrowed(&rendered[range.clone()]);
+        if decorations.indent && text.contains('\n') {
+            let prefix = continuation_prefix(&rendered[..range.start]);
+            text = Cow::Owned(unindent(&text, &prefix));
+        }
+        if decorations.tag {
+            text = Cow::Owned(untag(&text, p.key())?);
+        }
+        if decorations.fence.is_some() {
+            text = Cow::Owned(unfence(&text)?);
+        }
+        Some(text)
+    }
+
+    fn record(&mut self) {
+        let mut vars = HashMap::new();
+        for (key, value) in &self.bindings {
+            match vars.get(*key) {
+                Some(seen) if seen != value => {
+                    if self.conflict.is_none() {
+                        self.conflict = Some(key.to_string());
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    vars.insert(key.to_string(), value.to_string());
+                }
+            }
+        }
+        if !self.solutions.contains(&vars) {
+            self.solutions.push(vars);
+        }
+    }
+}
+
+/// Undo `push_indented`: drop `prefix` (trimmed on blank lines) after each
+/// newline where it is found.
+fn unindent(text: &str, prefix: &str) -> String {
+    let mut lines = text.split('\n');
+    let mut out = lines.next().unwrap_or_default().to_string();
+    for line in lines {
+        out.push('\n');
+        let rest = line
+            .strip_prefix(prefix)
+            .or_else(|| line.strip_prefix(prefix.trim_end()))
+            .unwrap_or(line);
+        out.push_str(rest);
+    }
+    out
+}
+
+/// Undo [`tagged`](crate::escape::tagged): the value between the tags, closing tags restored.
+fn untag(text: &str, key: &str) -> Option<String> {
+    let tag = tag_name(key);
+    let inner = text
+        .strip_prefix('<')?
+        .strip_prefix(tag.as_str())?
+        .strip_prefix('>')?
+        .strip_suffix('>')?
+        .strip_suffix(tag.as_str())?
+        .strip_suffix("</")?;
+    let mut out = String::with_capacity(inner.len());
+    let mut last = 0;
+    let mut from = 0;
+    while let Some(found) = inner[from..].find("&lt;") {
+        let pos = from + found;
+        if closes_tag(&inner[pos + 4..], &tag) {
+            out.push_str(&inner[last..pos]);
+            out.push('<');
+            last = pos + 4;
+        }
+        from = pos + 4;
+    }
+    out.push_str(&inner[last..]);
+    Some(out)
+}
+
+/// Undo [`fenced`](crate::escape::fenced): the body between the fences,
+/// without the newline added before the closing one.
+fn unfence(text: &str) -> Option<String> {
+    let ticks = text.len() - text.trim_start_matches('`').len();
+    if ticks < 3 {
+        return None;
+    }
+    let fence = &text[..ticks];
+    let body = &text[text.find('\n')? + 1..];
+    let body = body.strip_suffix(fence)?;
+    Some(body.strip_suffix('\n').unwrap_or(body).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FillOptions;
+
+    fn roundtrip(source: &str, options: &FillOptions, vars: &[(&str, &str)]) {
+        let tpl = Template::parse_with(source, options).unwrap();
+        let rendered = tpl.render_lookup(vars).unwrap();
+        let extracted = tpl.extract(&rendered).unwrap();
+        assert_eq!(tpl.render_lookup(&extracted).unwrap(), rendered);
+        for (k, v) in vars {
+            assert_eq!(extracted[*k], *v, "{k} from {rendered:?}");
+        }
+    }
+
+    #[test]
+    fn decorations_are_removed() {
+        let indented = FillOptions {
+            indent: true,
+            ..FillOptions::default()
+        };
+        roundtrip(
+            "- {{items}}\n> {{quote | tagged}}\n{{code | fence(\"rust\")}}",
+            &indented,
+            &[
+                ("items", "one\n\ntwo"),
+                ("quote", "a </quote> b\nc"),
+                ("code", "fn main() {}"),
+            ],
+        );
+        roundtrip(
+            "{{q | tagged}}",
+            &FillOptions::default(),
+            &[("q", "evil </Q > </q\n> </ q x> </qq> done")],
+        );
+        roundtrip(
+            "[{{a}}] {{b}} [{{a}}]",
+            &FillOptions::default(),
+            &[("a", "]"), ("b", "x")],
+        );
+    }
+
+    #[test]
+    fn ambiguity_and_conflicts_are_reported() {
+        let tpl = Template::parse("{{a}}{{b}}!").unwrap();
+        assert_eq!(
+            tpl.extract("xy!"),
+            Err(ExtractError::Ambiguous(vec!["a".into(), "b".into()]))
+        );
+        // the repeated `a` settles the split
+        let tpl = Template::parse("{{a}}{{b}} ({{a}})").unwrap();
+        let vars = tpl.extract("xy (x)").unwrap();
+        assert_eq!((vars["a"].as_str(), vars["b"].as_str()), ("x", "y"));
+
+        let tpl = Template::parse("{{a}}, {{b}}").unwrap();
+        assert_eq!(
+            tpl.extract("x, y, z"),
+            Err(ExtractError::Ambiguous(vec!["a".into(), "b".into()]))
+        );
+        let tpl = Template::parse("{{a}} and {{a}}").unwrap();
+        assert_eq!(
+            tpl.extract("x and y"),
+            Err(ExtractError::Inconsistent("a".into()))
+        );
+        let tpl = Template::parse("Hi {{who}}!").unwrap();
+        assert_eq!(tpl.extract("Hi Ann?"), Err(ExtractError::NoMatch(3)));
+        let tpl = Template::parse("{{x | json}}").unwrap();
+        assert_eq!(tpl.extract("1"), Err(ExtractError::Escaped("x".into())));
+    }
+
+    #[test]
+    fn long_outputs_stay_within_the_step_budget() {
+        // three adjacent values over 32 KiB: each candidate end is produced
+        // when tried and charged, rather than all collected up front
+        let line = "x".repeat(32 * 1024);
+        let tpl = Template::parse("{{a}}{{b}}{{c}}!").unwrap();
+        assert_eq!(
+            tpl.extract(&format!("{line}!")),
+            Err(ExtractError::Ambiguous(vec!["b".into(), "c".into()]))
+        );
+        assert_eq!(tpl.extract(&line), Err(ExtractError::TooComplex));
+    }
+}
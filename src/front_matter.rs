@@ -0,0 +1,507 @@
+//! Template front matter: the variables a template expects, declared at its
+//! top.
+//!
+//! A template may start with a TOML block between `+++` lines or a YAML block
+//! between `---` lines. Its `variables` table describes each variable: a
+//! `description`, a `type` (`string`, `integer`, `number` or `boolean`), a
+//! `default`, whether it is `required`, an `example`, whether it is `tagged`
+//! or `secret`, and validation rules (`min_words`, `max_words`,
+//! `min_chars`, `max_chars`, `one_of`, integer bounds `min` / `max`,
+//! `pattern` with the `regex` feature, and the `semver`, `spdx` and
+//! `non_empty` flags; see [`crate::validate`]). The block is only recognised
+//! when both delimiters are there and its first entry is that `variables`
+//! table, its only allowed key: a Markdown template opening with a `---` rule
+//! (or a Hugo-style block) stays body text. The body after it is the template
+//! proper.
+//!
+//! [`split`](crate::front_matter::split) needs no feature; reading the block
+//! with [`parse`](crate::front_matter::parse) needs the `serde` feature.
+//!
+//! ```text
+//! +++
+//! [variables.short_description]
+//! description = "What the crate does, in one sentence"
+//! required = true
+//! max_words = 30
+//! example = "A tiny prompt filler"
+//!
+//! [variables.msrv]
+//! default = "1.90"
+//! +++
+//! Résumé: {{short_description}} (MSRV {{msrv}})
+//! ```
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Result;
+
+#[cfg(feature = "regex")]
+use crate::validate::Pattern;
+use crate::validate::{
+    IntRange, MaxChars, MaxWords, MinChars, MinWords, NonEmpty, OneOf, Rule, SemVer, Spdx,
+    ValidationError,
+};
+use crate::FillOptions;
+
+/// Syntax of a front matter block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Between `+++` lines.
+    Toml,
+    /// Between `---` lines.
+    Yaml,
+}
+
+/// Split `source` into its front matter (format and raw text) and body;
+/// `None` and the whole source when it has none, or when the block between
+/// the delimiters does not start with the `variables` table.
+///
+/// # Examples
+///
+/// ```
+/// use fill_prompt::front_matter::{split, Format};
+///
+/// let (front, body) = split("---\nvariables: {}\n---\nHi {{who}}");
+/// assert_eq!(front, Some((Format::Yaml, "variables: {}\n")));
+/// assert_eq!(body, "Hi {{who}}");
+/// assert_eq!(split("--- not front matter").1, "--- not front matter");
+///
+/// // Markdown horizontal rules, not a front matter
+/// let doc = "---\nRole: {{role}}\n---\nAnswer.";
+/// assert_eq!(split(doc), (None, doc));
+/// ```
+pub fn split(source: &str) -> (Option<(Format, &str)>, &str) {
+    let first = source.split_inclusive('\n').next().unwrap_or_default();
+    let format = match first.trim_end_matches(['\n', '\r']) {
+        "+++" => Format::Toml,
+        "---" => Format::Yaml,
+        _ => return (None, source),
+    };
+    let delimiter = &first[..3];
+    let mut at = first.len();
+    for line in source[first.len()..].split_inclusive('\n') {
+        if line.trim_end_matches(['\n', '\r']) == delimiter {
+            let raw = &source[first.len()..at];
+            if !declares_variables(raw) {
+                break;
+            }
+            return (Some((format, raw)), &source[at + line.len()..]);
+        }
+        at += line.len();
+    }
+    (None, source)
+}
+
+/// Whether the first entry of a block (blank and `#` comment lines skipped)
+/// is the `variables` table: `variables:` in YAML, `[variables...]`,
+/// `variables.x...` or `variables =` in TOML.
+fn declares_variables(raw: &str) -> bool {
+    let Some(line) = raw
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+    else {
+        return false;
+    };
+    let rest = line
+        .strip_prefix("[variables")
+        .or_else(|| line.strip_prefix("variables"));
+    rest.is_some_and(|rest| rest.trim_start().starts_with([':', '.', '=', ']']))
+}
+
+/// Type of a declared variable; values are checked against it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VarType {
+    /// Any text.
+    #[default]
+    String,
+    /// A whole number (`i64`).
+    Integer,
+    /// Any number (`f64`).
+    Number,
+    /// `true` or `false`.
+    Boolean,
+}
+
+impl VarType {
+    /// The type named `name` in a front matter.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "string" => Some(VarType::String),
+            "integer" => Some(VarType::Integer),
+            "number" => Some(VarType::Number),
+            "boolean" => Some(VarType::Boolean),
+            _ => None,
+        }
+    }
+
+    /// Its name in a front matter.
+    pub fn name(self) -> &'static str {
+        match self {
+            VarType::String => "string",
+            VarType::Integer => "integer",
+            VarType::Number => "number",
+            VarType::Boolean => "boolean",
+        }
+    }
+
+    fn accepts(self, value: &str) -> bool {
+        match self {
+            VarType::String => true,
+            VarType::Integer => value.parse::<i64>().is_ok(),
+            VarType::Number => value.parse::<f64>().is_ok(),
+            VarType::Boolean => matches!(value, "true" | "false"),
+        }
+    }
+}
+
+impl Rule for VarType {
+    fn id(&self) -> &'static str {
+        "type"
+    }
+
+    fn check(&self, value: &str) -> Result<(), ValidationError> {
+        if self.accepts(value) {
+            Ok(())
+        } else {
+            Err(ValidationError::WrongType(self.name().to_string()))
+        }
+    }
+}
+
+/// Validation rules of a declared variable.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VarRules {
+    /// Must not be empty or blank.
+    pub non_empty: bool,
+    /// Fewest words (whitespace separated).
+    pub min_words: Option<usize>,
+    /// Most words.
+    pub max_words: Option<usize>,
+    /// Fewest characters.
+    pub min_chars: Option<usize>,
+    /// Most characters.
+    pub max_chars: Option<usize>,
+    /// Smallest integer value (the value must then be an integer).
+    pub min: Option<i64>,
+    /// Largest integer value.
+    pub max: Option<i64>,
+    /// Regular expression the value must match.
+    #[cfg(feature = "regex")]
+    pub pattern: Option<Pattern>,
+    /// Allowed values; any value when empty.
+    pub one_of: Vec<String>,
+    /// Must be a semver version.
+    pub semver: bool,
+    /// Must be an SPDX license expression.
+    pub spdx: bool,
+}
+
+/// What a front matter says about one variable.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VarSpec {
+    /// What the variable is for.
+    pub description: Option<String>,
+    /// Expected type.
+    pub kind: VarType,
+    /// Value used when none is provided.
+    pub default: Option<String>,
+    /// Must be provided (exclusive with `default`).
+    pub required: bool,
+    /// A typical value, for help texts.
+    pub example: Option<String>,
+    /// Wrap the value in `<name>…</name>` (see [`FillOptions::tagged`]).
+    pub tagged: bool,
+    /// Never echo the value (see [`crate::secret`]).
+    pub secret: bool,
+    /// Validation limits.
+    pub rules: VarRules,
+}
+
+impl VarSpec {
+    /// The type and the limits as [`Rule`]s, in the order they are checked:
+    /// `non_empty`, the type (unless `string`), integer bounds, words,
+    /// characters, `pattern`, `one_of`, `semver`, `spdx`.
+    pub fn rules(&self) -> Vec<Box<dyn Rule>> {
+        let r = &self.rules;
+        let mut rules: Vec<Box<dyn Rule>> = Vec::new();
+        if r.non_empty {
+            rules.push(Box::new(NonEmpty));
+        }
+        if self.kind != VarType::String {
+            rules.push(Box::new(self.kind));
+        }
+        if r.min.is_some() || r.max.is_some() {
+            rules.push(Box::new(IntRange {
+                min: r.min,
+                max: r.max,
+            }));
+        }
+        rules.extend(r.max_words.map(|n| Box::new(MaxWords(n)) as Box<dyn Rule>));
+        rules.extend(r.min_words.map(|n| Box::new(MinWords(n)) as Box<dyn Rule>));
+        rules.extend(r.max_chars.map(|n| Box::new(MaxChars(n)) as Box<dyn Rule>));
+        rules.extend(r.min_chars.map(|n| Box::new(MinChars(n)) as Box<dyn Rule>));
+        #[cfg(feature = "regex")]
+        rules.extend(r.pattern.clone().map(|p| Box::new(p) as Box<dyn Rule>));
+        if !r.one_of.is_empty() {
+            rules.push(Box::new(OneOf(r.one_of.clone())));
+        }
+        if r.semver {
+            rules.push(Box::new(SemVer));
+        }
+        if r.spdx {
+            rules.push(Box::new(Spdx));
+        }
+        rules
+    }
+
+    /// Check `value` against [`VarSpec::rules`].
+    ///
+    /// # Errors
+    ///
+    /// The first rule `value` breaks; errors never contain the value.
+    pub fn validate(&self, value: &str) -> Result<(), ValidationError> {
+        self.rules().iter().try_for_each(|rule| rule.check(value))
+    }
+}
+
+/// A parsed front matter.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrontMatter {
+    /// Declared variables, by name.
+    pub variables: BTreeMap<String, VarSpec>,
+}
+
+impl FrontMatter {
+    /// Insert the defaults of the variables missing from `vars`.
+    pub fn apply_defaults(&self, vars: &mut HashMap<String, String>) {
+        for (name, spec) in &self.variables {
+            if let Some(default) = &spec.default {
+                vars.entry(name.clone()).or_insert_with(|| default.clone());
+            }
+        }
+    }
+
+    /// Add the variables declared `tagged` to [`FillOptions::tagged`].
+    pub fn apply_options(&self, options: &mut FillOptions) {
+        for (name, spec) in &self.variables {
+            if spec.tagged {
+                options.tagged.insert(name.clone());
+            }
+        }
+    }
+
+    /// Names of the variables declared `secret`.
+    pub fn secrets(&self) -> impl Iterator<Item = &str> {
+        self.variables
+            .iter()
+            .filter(|(_, spec)| spec.secret)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Required variables missing from `vars`.
+    pub fn missing<'f>(&'f self, vars: &HashMap<String, String>) -> Vec<&'f str> {
+        self.variables
+            .iter()
+            .filter(|(name, spec)| spec.required && !vars.contains_key(name.as_str()))
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+
+/// Split `source` with [`split`] and read its front matter, if any.
+///
+/// # Errors
+///
+/// Invalid TOML / YAML, unknown fields or types, an invalid `pattern` (or
+/// one without the `regex` feature), a default that does not validate, a
+/// variable both `required` and with a `default`; or a front matter without
+/// the `serde` feature.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "serde")] {
+/// use fill_prompt::front_matter::{parse, VarType};
+///
+/// let source = "+++\n[variables.count]\ntype = \"integer\"\ndefault = 3\n+++\n{{count}} ideas";
+/// let (front, body) = parse(source).unwrap();
+/// let count = &front.unwrap().variables["count"];
+/// assert_eq!((count.kind, count.default.as_deref()), (VarType::Integer, Some("3")));
+/// assert_eq!(body, "{{count}} ideas");
+/// # }
+/// ```
+pub fn parse(source: &str) -> Result<(Option<FrontMatter>, &str)> {
+    match split(source) {
+        (Some((format, raw)), body) => Ok((Some(parse_block(format, raw)?), body)),
+        (None, body) => Ok((None, body)),
+    }
+}
+
+/// Read the text of a front matter block, delimiters excluded.
+#[cfg(feature = "serde")]
+pub(crate) fn parse_block(format: Format, raw: &str) -> Result<FrontMatter> {
+    use anyhow::{anyhow, bail, Context};
+
+    /// Scalar as written in TOML / YAML.
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Scalar {
+        Bool(bool),
+        Integer(i64),
+        Float(f64),
+        String(String),
+    }
+
+    impl Scalar {
+        fn into_string(self) -> String {
+            match self {
+                Scalar::Bool(b) => b.to_string(),
+                Scalar::Integer(i) => i.to_string(),
+                Scalar::Float(f) => f.to_string(),
+                Scalar::String(s) => s,
+            }
+        }
+    }
+
+    #[derive(serde::Deserialize, Default)]
+    #[serde(default, deny_unknown_fields)]
+    struct RawFrontMatter {
+        variables: BTreeMap<String, RawVarSpec>,
+    }
+
+    #[derive(serde::Deserialize, Default)]
+    #[serde(default, deny_unknown_fields)]
+    struct RawVarSpec {
+        description: Option<String>,
+        #[serde(rename = "type")]
+        kind: Option<String>,
+        default: Option<Scalar>,
+        required: bool,
+        example: Option<Scalar>,
+        tagged: bool,
+        secret: bool,
+        min_words: Option<usize>,
+        max_words: Option<usize>,
+        min_chars: Option<usize>,
+        max_chars: Option<usize>,
+        one_of: Vec<Scalar>,
+        min: Option<i64>,
+        max: Option<i64>,
+        pattern: Option<String>,
+        semver: bool,
+        spdx: bool,
+        non_empty: bool,
+    }
+
+    let raw: RawFrontMatter = match format {
+        Format::Toml => toml::from_str(raw).context("invalid TOML front matter")?,
+        Format::Yaml if raw.trim().is_empty() => RawFrontMatter::default(),
+        Format::Yaml => serde_yaml::from_str(raw).context("invalid YAML front matter")?,
+    };
+    let mut front = FrontMatter::default();
+    for (name, var) in raw.variables {
+        let kind = match var.kind.as_deref() {
+            None => VarType::String,
+            Some(kind) => VarType::from_name(kind)
+                .ok_or_else(|| anyhow!("unknown type `{}` for variable `{}`", kind, name))?,
+        };
+        #[cfg(feature = "regex")]
+        let pattern = var
+            .pattern
+            .map(|p| Pattern::new(&p))
+            .transpose()
+            .with_context(|| format!("invalid pattern for variable `{}`", name))?;
+        #[cfg(not(feature = "regex"))]
+        if var.pattern.is_some() {
+            bail!(
+                "variable `{}` has a pattern, which needs the \"regex\" feature",
+                name
+            );
+        }
+        let spec = VarSpec {
+            description: var.description,
+            kind,
+            default: var.default.map(Scalar::into_string),
+            required: var.required,
+            example: var.example.map(Scalar::into_string),
+            tagged: var.tagged,
+            secret: var.secret,
+            rules: VarRules {
+                min_words: var.min_words,
+                max_words: var.max_words,
+                min_chars: var.min_chars,
+                max_chars: var.max_chars,
+                one_of: var.one_of.into_iter().map(Scalar::into_string).collect(),
+                min: var.min,
+                max: var.max,
+                #[cfg(feature = "regex")]
+                pattern,
+                semver: var.semver,
+                spdx: var.spdx,
+                non_empty: var.non_empty,
+            },
+        };
+        if spec.required && spec.default.is_some() {
+            bail!("variable `{}` is required and has a default", name);
+        }
+        if let Some(default) = &spec.default {
+            spec.validate(default)
+                .map_err(|e| anyhow!("invalid default for variable `{}`: {}", name, e))?;
+        }
+        front.variables.insert(name, spec);
+    }
+    Ok(front)
+}
+
+#[cfg(not(feature = "serde"))]
+pub(crate) fn parse_block(_format: Format, _raw: &str) -> Result<FrontMatter> {
+    Err(anyhow::anyhow!(
+        "front matter and schema files need the \"serde\" feature. Rebuild with `--features serde`."
+    ))
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_and_yaml_declare_the_same_variables() {
+        let toml = "+++\n[variables.tone]\ndescription = \"Voice\"\none_of = [\"formal\", \"casual\"]\ndefault = \"formal\"\ntagged = true\n\n[variables.token]\nsecret = true\nrequired = true\nmin_chars = 4\n+++\nbody";
+        let yaml = "---\nvariables:\n  tone:\n    description: Voice\n    one_of: [formal, casual]\n    default: formal\n    tagged: true\n  token:\n    secret: true\n    required: true\n    min_chars: 4\n---\nbody";
+        let (a, body) = parse(toml).unwrap();
+        let (b, _) = parse(yaml).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(body, "body");
+
+        let front = a.unwrap();
+        let mut vars = HashMap::new();
+        assert_eq!(front.missing(&vars), ["token"]);
+        front.apply_defaults(&mut vars);
+        assert_eq!(vars["tone"], "formal");
+        vars.insert("token".into(), "abc".into());
+        let report = crate::validate::Schema::from_front_matter(&front).report(&vars);
+        assert_eq!(report.violations().len(), 1);
+        assert_eq!(
+            report.violations()[0].to_string(),
+            "token: trop court: 3 caractères (min 4) [min_chars]"
+        );
+        assert_eq!(front.secrets().collect::<Vec<_>>(), ["token"]);
+        let mut options = FillOptions::default();
+        front.apply_options(&mut options);
+        assert!(options.tagged.contains("tone"));
+    }
+
+    #[test]
+    fn rejects_inconsistent_declarations() {
+        for bad in [
+            "+++\n[variables.n]\ntype = \"integer\"\ndefault = \"x\"\n+++\n",
+            "+++\n[variables.n]\nrequired = true\ndefault = 1\n+++\n",
+            "+++\n[variables.n]\ntype = \"date\"\n+++\n",
+            "+++\n[variables.n]\nmax_word = 3\n+++\n",
+            "+++\n[variables.n]\npattern = \"(\"\n+++\n",
+            "+++\n[variables.n]\nmin = 2\ndefault = 1\n+++\n",
+        ] {
+            assert!(parse(bad).is_err(), "{bad}");
+        }
+    }
+}
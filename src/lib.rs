@@ -12,15 +12,65 @@
 //! assert!(filled.contains("hello_world"));
 //! assert!(filled.contains("Hello, Rust!"));
 //! ```
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
 use thiserror::Error;
 
+use escape::Escape;
+use normalize::Normalization;
+
 /// expose the validation helpers implemented in src/validate.rs
 pub mod validate;
 
 /// expose variable loader (JSON/YAML/TOML) — feature-gated on "serde"
 pub mod vars;
 
+/// expose the template parser and renderer implemented in src/template.rs
+pub mod template;
+
+/// expose borrowed variable sources (HashMap, BTreeMap, pair slices)
+pub mod lookup;
+
+/// expose the named template registry (partials, inheritance, hot reload)
+pub mod registry;
+
+/// expose incremental re-rendering for live previews
+pub mod live;
+
+/// expose source maps from rendered output back to templates and variables
+pub mod source_map;
+
+/// expose reverse extraction of variables from a rendered output
+pub mod extract;
+
+/// expose template diffs and breaking-change detection
+pub mod diff;
+
+/// expose template front matter (declared variables, defaults, validation)
+pub mod front_matter;
+
+/// expose output escaping modes (JSON, YAML, shell, XML, Markdown)
+pub mod escape;
+
+/// expose Unicode normalisation (NFC/NFKC) and confusable detection
+pub mod normalize;
+
+/// expose prompt-injection heuristics for variable values
+pub mod safety;
+
+/// expose secret variables helpers (prefix, redacted Debug, zeroization)
+pub mod secret;
+
+/// expose PII / secret redaction — feature-gated on "redact"
+#[cfg(feature = "redact")]
+pub mod redact;
+
+/// expose missing / unused variable diagnostics implemented in src/diagnostics.rs
+pub mod diagnostics;
+
+pub use lookup::Lookup;
+pub use template::{Filter, RenderReport, Rendered, Template};
+
 /// Errors returned when filling templates.
 #[derive(Debug, Error, PartialEq, Eq)]
 pub enum FillError {
@@ -35,11 +85,189 @@ pub enum FillError {
     /// Variable not provided.
     #[error("missing variable `{0}`")]
     MissingVariable(String),
+
+    /// Placeholder name does not follow the key grammar (see [`KeySyntax::Strict`]).
+    /// Carries the offending name and its byte range in the template.
+    #[error("invalid placeholder name `{0}` at byte index {}..{}", .1.start, .1.end)]
+    InvalidPlaceholderName(String, Range<usize>),
+
+    /// Unknown filter, or filter called with the wrong arguments.
+    #[error("invalid filter `{0}` at byte index {}..{}", .1.start, .1.end)]
+    InvalidFilter(String, Range<usize>),
+
+    /// A variable value refers back to itself (see [`FillOptions::expand_values`]).
+    /// Carries the full path, e.g. `a -> b -> a`.
+    #[error("variable expansion cycle: {}", .0.join(" -> "))]
+    ExpansionCycle(Vec<String>),
+
+    /// Expanding the value of the variable nests deeper than
+    /// [`RenderLimits::max_recursion`].
+    #[error("expansion of `{0}` exceeds the depth limit ({1})")]
+    ExpansionTooDeep(String, usize),
+
+    /// The rendered output would exceed [`RenderLimits::max_output_bytes`].
+    #[error("rendered output exceeds the size limit ({0} bytes)")]
+    OutputTooLarge(usize),
+
+    /// The template (or an expanded value) has more placeholders than
+    /// [`RenderLimits::max_placeholders`].
+    #[error("template exceeds the placeholder limit ({0})")]
+    TooManyPlaceholders(usize),
+
+    /// Including the named template nests deeper than
+    /// [`RenderLimits::max_template_depth`].
+    #[error("include of `{0}` exceeds the depth limit ({1})")]
+    IncludeTooDeep(String, usize),
+
+    /// `{{> name}}` or `{{< name}}` refers to a template that is not in the
+    /// [`TemplateSet`](registry::TemplateSet) (or the template is rendered on
+    /// its own).
+    #[error("unknown template `{0}`")]
+    UnknownTemplate(String),
+
+    /// A `{{$block}}` or `{{< parent}}` section is never closed, or a
+    /// `{{/name}}` tag closes no section or another one. Carries the name and
+    /// the byte range of the tag.
+    #[error("unbalanced section `{0}` at byte index {}..{}", .1.start, .1.end)]
+    UnbalancedSection(String, Range<usize>),
+
+    /// Rendering performed more substitutions than
+    /// [`RenderLimits::max_substitutions`].
+    #[error("rendering exceeds the substitution limit ({0})")]
+    TooManySubstitutions(usize),
+
+    /// The value of the variable contains `{{` or `}}` and
+    /// [`ValuePolicy::Reject`] is in effect.
+    #[error("value of `{0}` contains template syntax")]
+    TemplateSyntaxInValue(String),
+
+    /// A redaction detector matched the value of the variable and
+    /// `redact::RedactMode::Fail` is in effect. Carries the variable and the
+    /// detector name, never the value itself.
+    #[error("value of `{0}` contains sensitive data ({1})")]
+    SensitiveValue(String, String),
+
+    /// Two variable names are the same once put in the
+    /// [`FillOptions::normalize`] form. Carries both spellings, in byte order.
+    #[error("variables {0:?} and {1:?} have the same name once normalised")]
+    NormalizedKeyCollision(String, String),
+}
+
+/// Which placeholder names the parser accepts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeySyntax {
+    /// Unicode alphanumerics, `_`, `-`, `.`, `[` and `]` only (plus combining
+    /// marks and other `XID_Continue` characters after the first); anything
+    /// else (spaces, newlines, braces...) is a [`FillError::InvalidPlaceholderName`].
+    #[default]
+    Strict,
+    /// Any non-empty trimmed text between `{{` and `}}` is a key (historical behaviour).
+    Permissive,
+}
+
+/// What to do with variable values that contain template delimiters (`{{`, `}}`).
+///
+/// Values often come from user input; once they are expanded or rendered a
+/// second time, such delimiters would be interpreted as placeholders.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ValuePolicy {
+    /// Insert values as they are (historical behaviour).
+    #[default]
+    Allow,
+    /// Break up every `{{` / `}}` run with spaces (`{ {`, `} }`) so the
+    /// output never contains template syntax coming from a value.
+    Escape,
+    /// Fail with [`FillError::TemplateSyntaxInValue`].
+    Reject,
+}
+
+/// Bounds on the work done for one template, for templates that cannot be
+/// trusted. Each limit has its own [`FillError`] variant.
+///
+/// The default keeps the historical behaviour (only recursion is bounded);
+/// [`RenderLimits::untrusted`] is a conservative preset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderLimits {
+    /// Size of the rendered output, in bytes ([`FillError::OutputTooLarge`]).
+    pub max_output_bytes: usize,
+    /// Placeholders in the template or in one expanded value
+    /// ([`FillError::TooManyPlaceholders`]).
+    pub max_placeholders: usize,
+    /// How many templates deep partials, layouts and block overrides may nest
+    /// ([`FillError::IncludeTooDeep`]).
+    pub max_template_depth: usize,
+    /// How many values deep [`FillOptions::expand_values`] may nest
+    /// ([`FillError::ExpansionTooDeep`]).
+    pub max_recursion: usize,
+    /// Substitutions performed by the render loop, those inside expanded
+    /// values included ([`FillError::TooManySubstitutions`]).
+    pub max_substitutions: usize,
+}
+
+impl RenderLimits {
+    /// Limits for templates received from outside: 1 MiB of output,
+    /// 10 000 placeholders, 4 levels of includes and of expansion,
+    /// 100 000 substitutions.
+    pub const fn untrusted() -> Self {
+        RenderLimits {
+            max_output_bytes: 1 << 20,
+            max_placeholders: 10_000,
+            max_template_depth: 4,
+            max_recursion: 4,
+            max_substitutions: 100_000,
+        }
+    }
+}
+
+impl Default for RenderLimits {
+    fn default() -> Self {
+        RenderLimits {
+            max_output_bytes: usize::MAX,
+            max_placeholders: usize::MAX,
+            max_template_depth: 8,
+            max_recursion: 8,
+            max_substitutions: usize::MAX,
+        }
+    }
+}
+
+/// Options controlling how templates are parsed and rendered.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FillOptions {
+    /// Placeholder name grammar.
+    pub key_syntax: KeySyntax,
+    /// Render variable values against the same variables before inserting
+    /// them, so `repository = "https://github.com/{{org_name}}"` works.
+    /// Off by default: values are inserted verbatim.
+    pub expand_values: bool,
+    /// Guard against template syntax inside values. `Escape` and `Reject`
+    /// take precedence over [`expand_values`](Self::expand_values).
+    pub value_policy: ValuePolicy,
+    /// Escaping applied to every inserted value unless a placeholder filter
+    /// (`{{x | json}}`, `{{x | raw}}`...) says otherwise.
+    pub escape: Escape,
+    /// Re-indent continuation lines of multi-line values to the column where
+    /// the placeholder sits, keeping `>` quote markers (`{{x | indent}}` /
+    /// `{{x | noindent}}` per placeholder).
+    pub indent: bool,
+    /// Wrap every value in `<key>…</key>` tags (see [`escape::tagged`]).
+    pub tag_all: bool,
+    /// Variables whose values are wrapped in `<key>…</key>` tags; typically
+    /// filled from [`vars::VarSettings::tagged`].
+    pub tagged: BTreeSet<String>,
+    /// Unicode normal form applied to the template (literals and keys) and to
+    /// variable names and values before rendering; invisible characters are
+    /// stripped at the same time. Off by default.
+    pub normalize: Normalization,
+    /// Resource limits (output size, placeholders, nesting, substitutions).
+    pub limits: RenderLimits,
 }
 
 /// Fill `template` by replacing occurrences of `{{key}}` with `vars[key]`.
 ///
 /// - Placeholders are delimited by `{{` and `}}` (double braces).
+/// - Keys follow the strict grammar (see [`KeySyntax`]); use [`fill_template_with`]
+///   to opt back into the permissive one.
 /// - Returns `Err(FillError::MissingVariable(_))` when a placeholder has no mapping.
 /// - No panic, no `unsafe`.
 ///
@@ -59,58 +287,70 @@ where
     K: AsRef<str>,
     V: AsRef<str>,
 {
-    // Collect variables into owned HashMap<String,String>
-    let mut map: HashMap<String, String> = HashMap::new();
-    for (k, v) in vars {
-        map.insert(k.as_ref().to_string(), v.as_ref().to_string());
+    fill_template_with(template, vars, &FillOptions::default())
+}
+
+/// Same as [`fill_template`], with explicit [`FillOptions`].
+///
+/// # Examples
+///
+/// ```
+/// use fill_prompt::{fill_template_with, FillOptions, KeySyntax};
+/// let opts = FillOptions { key_syntax: KeySyntax::Permissive, ..Default::default() };
+/// let out = fill_template_with("{{ my key }}", [("my key", "v")], &opts).unwrap();
+/// assert_eq!(out, "v");
+/// ```
+pub fn fill_template_with<I, K, V>(
+    template: &str,
+    vars: I,
+    options: &FillOptions,
+) -> Result<String, FillError>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let vars: Vec<(K, V)> = vars.into_iter().collect();
+    // plain substitutions skip building a `Template` and copying the values
+    let borrowed: HashMap<&str, &str> =
+        vars.iter().map(|(k, v)| (k.as_ref(), v.as_ref())).collect();
+    if let Some(out) = template::render_direct(template, &borrowed, options) {
+        return Ok(out);
     }
+    let map: HashMap<String, String> = vars
+        .iter()
+        .map(|(k, v)| (k.as_ref().to_string(), v.as_ref().to_string()))
+        .collect();
+    Template::parse_with(template, options)?.render(&map)
+}
 
-    let mut out = String::with_capacity(template.len());
-    let bytes = template.as_bytes();
-    let mut i = 0usize;
-    while i < bytes.len() {
-        // look for "{{"
-        if i + 1 < bytes.len() && bytes[i] == b'{' && bytes[i + 1] == b'{' {
-            let start = i;
-            i += 2; // skip "{{"
-            let key_start = i;
-            // scan until "}}"
-            let mut found = false;
-            while i + 1 < bytes.len() {
-                if bytes[i] == b'}' && bytes[i + 1] == b'}' {
-                    found = true;
-                    break;
-                }
-                i += 1;
-            }
-            if !found {
-                return Err(FillError::UnclosedPlaceholder(start));
-            }
-            // `trim()` returns &str
-            let key = template[key_start..i].trim();
-            if key.is_empty() {
-                return Err(FillError::EmptyPlaceholder(key_start));
-            }
-            // move i past "}}"
-            i += 2;
-            match map.get(key) {
-                Some(val) => out.push_str(val),
-                None => return Err(FillError::MissingVariable(key.to_string())),
-            }
-        } else {
-            // append single char (valid UTF-8 since template is &str)
-            // avoid unwrap for clippy
-            let mut chars = template[i..].chars();
-            if let Some(ch) = chars.next() {
-                out.push(ch);
-                i += ch.len_utf8();
-            } else {
-                // should not happen because i < bytes.len(), but guard defensively
-                break;
-            }
-        }
+/// Fill `template` from variables the caller already holds, without copying
+/// them: see [`Lookup`] for the accepted sources.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+/// let mut vars = HashMap::new();
+/// vars.insert("who".to_string(), "Alice".to_string());
+/// assert_eq!(fill_prompt::render_with("Hi {{who}}", &vars).unwrap(), "Hi Alice");
+/// // `vars` is still ours
+/// assert_eq!(vars.len(), 1);
+/// ```
+pub fn render_with<L: Lookup + ?Sized>(template: &str, vars: &L) -> Result<String, FillError> {
+    render_with_options(template, vars, &FillOptions::default())
+}
+
+/// Same as [`render_with`], with explicit [`FillOptions`].
+pub fn render_with_options<L: Lookup + ?Sized>(
+    template: &str,
+    vars: &L,
+    options: &FillOptions,
+) -> Result<String, FillError> {
+    if let Some(out) = template::render_direct(template, vars, options) {
+        return Ok(out);
     }
-    Ok(out)
+    Template::parse_with(template, options)?.render_lookup(vars)
 }
 
 #[cfg(test)]
@@ -161,6 +401,17 @@ mod tests {
         }
     }
 
+    #[test]
+    fn invalid_placeholder_name_error() {
+        let tpl = "Crate {{crate name}}";
+        let vars: HashMap<String, String> = HashMap::new();
+        let err = fill_template(tpl, vars).unwrap_err();
+        assert_eq!(
+            err,
+            FillError::InvalidPlaceholderName("crate name".to_string(), 8..18)
+        );
+    }
+
     #[test]
     fn adjacent_placeholders() {
         let tpl = "{{a}}{{b}}{{c}}";
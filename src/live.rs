@@ -0,0 +1,264 @@
+//! Incremental re-rendering for live previews.
+//!
+//! [`LiveRender::new`](crate::live::LiveRender::new) renders a template once
+//! and records, for every placeholder, the byte range its (escaped, decorated)
+//! value occupies in the output. When some variables change,
+//! [`LiveRender::rerender_changed`](crate::live::LiveRender::rerender_changed)
+//! renders only the placeholders that depend on them, patches the output in
+//! place and returns the replacements it made, e.g. to drive a UI diff.
+//!
+//! A placeholder depends on its own key; with
+//! [`FillOptions::expand_values`](crate::FillOptions::expand_values), a value
+//! that is itself a template may use any key and is always re-rendered; an
+//! indented multi-line value is re-rendered when something before it on the
+//! same line changed.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::collections::HashMap;
+//! use fill_prompt::live::LiveRender;
+//! use fill_prompt::Template;
+//!
+//! let tpl = Template::parse("Dear {{name}},\n{{body}}\n-- {{name}}").unwrap();
+//! let mut vars = HashMap::from([
+//!     ("name".to_string(), "Ann".to_string()),
+//!     ("body".to_string(), "See you soon.".to_string()),
+//! ]);
+//! let (mut live, mut output) = LiveRender::new(&tpl, &vars).unwrap();
+//!
+//! vars.insert("name".to_string(), "Bob".to_string());
+//! let edits = live.rerender_changed(&mut output, ["name"], &vars).unwrap();
+//! assert_eq!(output, "Dear Bob,\nSee you soon.\n-- Bob");
+//! assert_eq!(edits.len(), 2);
+//! assert_eq!((edits[0].range.clone(), edits[0].text.as_str()), (5..8, "Bob"));
+//! ```
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+use crate::lookup::Lookup;
+use crate::template::Placeholder;
+use crate::{FillError, Template};
+
+/// Where the value of a placeholder landed in the output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slot {
+    /// The placeholder.
+    pub placeholder: Placeholder,
+    /// Byte range of its value, decorations included, in the output.
+    pub range: Range<usize>,
+}
+
+/// A replacement made by [`LiveRender::rerender_changed`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    /// Byte range replaced, in the output as it was before the call.
+    pub range: Range<usize>,
+    /// The text now in its place.
+    pub text: String,
+}
+
+/// A rendered template that knows which output ranges came from which
+/// placeholder.
+#[derive(Debug, Clone)]
+pub struct LiveRender<'t> {
+    template: &'t Template,
+    slots: Vec<Slot>,
+}
+
+impl<'t> LiveRender<'t> {
+    /// Render `template` with `vars`, recording where each value lands.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Template::render`].
+    pub fn new<L: Lookup + ?Sized>(
+        template: &'t Template,
+        vars: &L,
+    ) -> Result<(Self, String), FillError> {
+        let (output, slots) = template.render_tracked(vars)?;
+        Ok((LiveRender { template, slots }, output))
+    }
+
+    /// The template being previewed.
+    pub fn template(&self) -> &'t Template {
+        self.template
+    }
+
+    /// Every rendered placeholder with its range in the current output, in
+    /// output order.
+    pub fn slots(&self) -> &[Slot] {
+        &self.slots
+    }
+
+    /// Re-render the placeholders affected by `changed_keys` with the new
+    /// `vars` and patch `output` (as returned by [`LiveRender::new`] or a
+    /// previous call) in place. Returns the replacements, in output order;
+    /// values that render the same are left out.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Template::render`]; `output` is then left as it was.
+    pub fn rerender_changed<L, I, K>(
+        &mut self,
+        output: &mut String,
+        changed_keys: I,
+        vars: &L,
+    ) -> Result<Vec<Edit>, FillError>
+    where
+        L: Lookup + ?Sized,
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        let changed: BTreeSet<String> = changed_keys
+            .into_iter()
+            .map(|k| k.as_ref().to_string())
+            .collect();
+        if changed.is_empty() {
+            return Ok(Vec::new());
+        }
+        let saved: Vec<Range<usize>> = self.slots.iter().map(|s| s.range.clone()).collect();
+        let mut edits = Vec::new();
+        let mut undo = Vec::new();
+        if let Err(e) = self.patch(output, &changed, vars, &mut edits, &mut undo) {
+            // later edits sit further right: undoing them first keeps the
+            // earlier ranges valid
+            for (range, text) in undo.into_iter().rev() {
+                output.replace_range(range, &text);
+            }
+            for (slot, range) in self.slots.iter_mut().zip(saved) {
+                slot.range = range;
+            }
+            return Err(e);
+        }
+        Ok(edits)
+    }
+
+    /// Body of [`LiveRender::rerender_changed`]; `undo` gets the range of
+    /// every replacement in the new output with the text it replaced.
+    fn patch<L: Lookup + ?Sized>(
+        &mut self,
+        output: &mut String,
+        changed: &BTreeSet<String>,
+        vars: &L,
+        edits: &mut Vec<Edit>,
+        undo: &mut Vec<(Range<usize>, String)>,
+    ) -> Result<(), FillError> {
+        let options = self.template.options();
+        let mut delta = 0isize;
+        let mut last_edit_end: Option<usize> = None;
+        for slot in &mut self.slots {
+            let old = slot.range.clone();
+            let start = old.start.wrapping_add_signed(delta);
+            let range = start..start + old.len();
+            slot.range = range.clone();
+
+            let p = &slot.placeholder;
+            let expands =
+                options.expand_values && vars.lookup(p.key()).is_some_and(|v| v.contains("{{"));
+            let realign = p.indent().unwrap_or(options.indent)
+                && last_edit_end.is_some_and(|end| !output[end..range.start].contains('\n'));
+            if !(changed.contains(p.key()) || expands || realign) {
+                continue;
+            }
+
+            // render after the start of the line, so indentation matches
+            let line_start = output[..range.start].rfind('\n').map_or(0, |i| i + 1);
+            let mut line = output[line_start..range.start].to_string();
+            self.template.render_placeholder(vars, p, &mut line)?;
+            let text = line.split_off(range.start - line_start);
+            if text == output[range.clone()] {
+                continue;
+            }
+
+            let new_range = range.start..range.start + text.len();
+            undo.push((new_range.clone(), output[range.clone()].to_string()));
+            output.replace_range(range, &text);
+            delta += text.len() as isize - old.len() as isize;
+            last_edit_end = Some(new_range.end);
+            slot.range = new_range;
+            edits.push(Edit { range: old, text });
+        }
+        let max = options.limits.max_output_bytes;
+        if output.len() > max {
+            return Err(FillError::OutputTooLarge(max));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FillOptions;
+    use std::collections::HashMap;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn patches_match_a_full_render() {
+        let options = FillOptions {
+            indent: true,
+            expand_values: true,
+            ..FillOptions::default()
+        };
+        let tpl = Template::parse_with(
+            "- {{a | json}} {{list}}\n{{link}} and {{b | tagged}}{{a}}",
+            &options,
+        )
+        .unwrap();
+        let mut map = vars(&[
+            ("a", "x"),
+            ("b", "y"),
+            ("list", "one\ntwo"),
+            ("link", "see {{b}}"),
+        ]);
+        let (mut live, mut output) = LiveRender::new(&tpl, &map).unwrap();
+        assert_eq!(live.slots().len(), 5);
+
+        for (key, value) in [
+            ("a", "longer \"a\""),
+            ("b", ""),
+            ("list", "1\n2\n3"),
+            ("a", ""),
+        ] {
+            map.insert(key.to_string(), value.to_string());
+            let before = output.clone();
+            let edits = live.rerender_changed(&mut output, [key], &map).unwrap();
+            assert_eq!(output, tpl.render(&map).unwrap(), "after changing {key}");
+            // the edits alone turn the old output into the new one
+            let mut replayed = before;
+            for edit in edits.iter().rev() {
+                replayed.replace_range(edit.range.clone(), &edit.text);
+            }
+            assert_eq!(replayed, output);
+        }
+        for slot in live.slots() {
+            assert!(output.is_char_boundary(slot.range.start));
+        }
+    }
+
+    #[test]
+    fn failed_rerender_leaves_output_untouched() {
+        let tpl = Template::parse("{{a}} {{b}}").unwrap();
+        let mut map = vars(&[("a", "1"), ("b", "2")]);
+        let (mut live, mut output) = LiveRender::new(&tpl, &map).unwrap();
+        map.insert("a".to_string(), "one".to_string());
+        map.remove("b");
+        assert_eq!(
+            live.rerender_changed(&mut output, ["a", "b"], &map),
+            Err(FillError::MissingVariable("b".to_string()))
+        );
+        assert_eq!(output, "1 2");
+        assert_eq!(live.slots()[1].range, 2..3);
+        assert!(live
+            .rerender_changed(&mut output, Vec::<&str>::new(), &map)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,92 @@
+//! Borrowed variable sources.
+//!
+//! [`fill_template`](crate::fill_template) copies every key and value into a
+//! fresh `HashMap<String, String>`. Callers who already hold their variables
+//! can hand them to [`render_with`](crate::render_with) or
+//! [`Template::render_lookup`](crate::Template::render_lookup) instead: both
+//! only ask the source for the keys the template uses, through [`Lookup`], and
+//! never copy it.
+//!
+//! `Lookup` is implemented for `HashMap` and `BTreeMap` (any string-like keys
+//! and values) and for slices of pairs, where the last pair for a key wins, as
+//! with `fill_template`.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::collections::BTreeMap;
+//! use fill_prompt::render_with;
+//!
+//! let vars = BTreeMap::from([("who", "Alice")]);
+//! assert_eq!(render_with("Hi {{who}}", &vars).unwrap(), "Hi Alice");
+//!
+//! let pairs = [("who", "Bob"), ("who", "Carol")];
+//! assert_eq!(render_with("Hi {{who}}", &pairs[..]).unwrap(), "Hi Carol");
+//! ```
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+
+/// A source of variable values, looked up by name.
+pub trait Lookup {
+    /// The value of `key`, if any.
+    fn lookup(&self, key: &str) -> Option<&str>;
+}
+
+impl<K, V, S> Lookup for HashMap<K, V, S>
+where
+    K: Borrow<str> + Hash + Eq,
+    V: AsRef<str>,
+    S: BuildHasher,
+{
+    fn lookup(&self, key: &str) -> Option<&str> {
+        self.get(key).map(AsRef::as_ref)
+    }
+}
+
+impl<K, V> Lookup for BTreeMap<K, V>
+where
+    K: Borrow<str> + Ord,
+    V: AsRef<str>,
+{
+    fn lookup(&self, key: &str) -> Option<&str> {
+        self.get(key).map(AsRef::as_ref)
+    }
+}
+
+impl<K, V> Lookup for [(K, V)]
+where
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    fn lookup(&self, key: &str) -> Option<&str> {
+        self.iter()
+            .rev()
+            .find(|(k, _)| k.as_ref() == key)
+            .map(|(_, v)| v.as_ref())
+    }
+}
+
+impl<T: Lookup + ?Sized> Lookup for &T {
+    fn lookup(&self, key: &str) -> Option<&str> {
+        (**self).lookup(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_and_pairs_agree() {
+        let owned = HashMap::from([("k".to_string(), "v".to_string())]);
+        let borrowed: BTreeMap<&str, &str> = BTreeMap::from([("k", "v")]);
+        let pairs = vec![("k", "old"), ("k", "v")];
+        let slice = pairs.as_slice();
+        let sources: [&dyn Lookup; 3] = [&owned, &borrowed, &slice];
+        for source in sources {
+            assert_eq!(source.lookup("k"), Some("v"));
+            assert_eq!(source.lookup("missing"), None);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! Unicode normalisation and confusable detection.
+//!
+//! Text pasted from documents mixes composed and decomposed accents
+//! (`é` vs `e` + U+0301), non-breaking spaces and zero-width characters, which
+//! breaks diffs, word counts and key lookups. With
+//! [`FillOptions::normalize`](crate::FillOptions::normalize) set, the template
+//! (literals and keys) and every variable (names and values) go through
+//! [`Normalization::apply`](crate::normalize::Normalization::apply) before rendering: invisible characters (see
+//! [`safety::is_invisible`](crate::safety::is_invisible)) are removed, then the
+//! text is put in NFC or NFKC form.
+//!
+//! [`confusables`](crate::normalize::confusables) lists characters that merely look like ASCII (Cyrillic `а`
+//! in a Latin word, fullwidth letters, non-breaking spaces); it only reports,
+//! it never rewrites.
+//!
+//! # Examples
+//!
+//! ```
+//! use fill_prompt::normalize::{confusables, Normalization};
+//!
+//! assert_eq!(Normalization::Nfc.apply("de\u{301}veloppeurs\u{200B}"), "développeurs");
+//! assert_eq!(Normalization::Nfkc.apply("ﬁchier\u{A0}1"), "fichier 1");
+//!
+//! let found = confusables("p\u{430}ypal");
+//! assert_eq!((found[0].found, found[0].looks_like), ('\u{430}', 'a'));
+//! ```
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+use unicode_normalization::{is_nfc, is_nfkc, UnicodeNormalization};
+
+use crate::safety::is_invisible;
+use crate::secret::ZeroizingVars;
+use crate::FillError;
+
+/// Which normal form text is put in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Normalization {
+    /// Leave text as it is.
+    #[default]
+    None,
+    /// Canonical composition: only changes how accents are encoded.
+    Nfc,
+    /// Compatibility composition: also folds ligatures, fullwidth forms,
+    /// non-breaking spaces, superscripts...
+    Nfkc,
+}
+
+impl Normalization {
+    /// The form named `none`, `nfc` or `nfkc`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Normalization::None),
+            "nfc" => Some(Normalization::Nfc),
+            "nfkc" => Some(Normalization::Nfkc),
+            _ => None,
+        }
+    }
+
+    /// `true` when [`apply`](Normalization::apply) would leave `text` as it is.
+    fn is_applied(self, text: &str) -> bool {
+        let clean = match self {
+            Normalization::None => return true,
+            Normalization::Nfc => is_nfc(text),
+            Normalization::Nfkc => is_nfkc(text),
+        };
+        clean && !text.chars().any(is_invisible)
+    }
+
+    /// Strip invisible characters and normalise `text`, borrowing it when
+    /// it is already clean.
+    pub fn apply(self, text: &str) -> Cow<'_, str> {
+        if self.is_applied(text) {
+            return Cow::Borrowed(text);
+        }
+        let visible = text.chars().filter(|c| !is_invisible(*c));
+        Cow::Owned(match self {
+            Normalization::Nfkc => visible.nfkc().collect(),
+            _ => visible.nfc().collect(),
+        })
+    }
+
+    /// Normalise both the names and the values of `vars`, borrowing the map
+    /// when it is already normalised (as when the caller did it first).
+    ///
+    /// # Errors
+    ///
+    /// [`FillError::NormalizedKeyCollision`] when two names only differ
+    /// before normalisation (composed and decomposed `é`): which value wins
+    /// would otherwise depend on the map's iteration order.
+    pub fn apply_vars(
+        self,
+        vars: &HashMap<String, String>,
+    ) -> Result<Cow<'_, HashMap<String, String>>, FillError> {
+        if vars
+            .iter()
+            .all(|(k, v)| self.is_applied(k) && self.is_applied(v))
+        {
+            return Ok(Cow::Borrowed(vars));
+        }
+        // values may be secret: a copy left behind by an error is wiped too
+        let mut out = ZeroizingVars(HashMap::with_capacity(vars.len()));
+        let mut spellings: HashMap<String, &str> = HashMap::with_capacity(vars.len());
+        for (k, v) in vars {
+            let key = self.apply(k).into_owned();
+            if let Some(other) = spellings.insert(key.clone(), k) {
+                let (a, b) = if other < k.as_str() {
+                    (other, k.as_str())
+                } else {
+                    (k.as_str(), other)
+                };
+                return Err(FillError::NormalizedKeyCollision(
+                    a.to_string(),
+                    b.to_string(),
+                ));
+            }
+            out.insert(key, self.apply(v).into_owned());
+        }
+        Ok(Cow::Owned(std::mem::take(&mut out.0)))
+    }
+}
+
+/// A character that looks like another, more common one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Confusable {
+    /// Byte range of the character.
+    pub span: Range<usize>,
+    /// The character found.
+    pub found: char,
+    /// What it passes for.
+    pub looks_like: char,
+}
+
+impl fmt::Display for Confusable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "U+{:04X} (bytes {}..{}) looks like {:?}",
+            self.found as u32, self.span.start, self.span.end, self.looks_like
+        )
+    }
+}
+
+/// Cyrillic and Greek letters drawn like Latin ones.
+const LOOKALIKES: &[(char, char)] = &[
+    ('а', 'a'),
+    ('е', 'e'),
+    ('о', 'o'),
+    ('р', 'p'),
+    ('с', 'c'),
+    ('у', 'y'),
+    ('х', 'x'),
+    ('і', 'i'),
+    ('ј', 'j'),
+    ('ѕ', 's'),
+    ('ԁ', 'd'),
+    ('А', 'A'),
+    ('В', 'B'),
+    ('Е', 'E'),
+    ('К', 'K'),
+    ('М', 'M'),
+    ('Н', 'H'),
+    ('О', 'O'),
+    ('Р', 'P'),
+    ('С', 'C'),
+    ('Т', 'T'),
+    ('Х', 'X'),
+    ('І', 'I'),
+    ('Ј', 'J'),
+    ('Ѕ', 'S'),
+    ('ο', 'o'),
+    ('ν', 'v'),
+    ('Α', 'A'),
+    ('Β', 'B'),
+    ('Ε', 'E'),
+    ('Ζ', 'Z'),
+    ('Η', 'H'),
+    ('Ι', 'I'),
+    ('Κ', 'K'),
+    ('Μ', 'M'),
+    ('Ν', 'N'),
+    ('Ο', 'O'),
+    ('Ρ', 'P'),
+    ('Τ', 'T'),
+    ('Υ', 'Y'),
+    ('Χ', 'X'),
+];
+
+/// Confusable characters in `text`, in order:
+///
+/// - Cyrillic / Greek lookalikes inside a word that also has ASCII letters
+///   (a fully Cyrillic or Greek word is left alone);
+/// - fullwidth ASCII forms (`ａ`, `！`...);
+/// - non-breaking and other fixed-width spaces.
+pub fn confusables(text: &str) -> Vec<Confusable> {
+    let mut out = Vec::new();
+    let mut word: Vec<(usize, char)> = Vec::new();
+    for (i, c) in text.char_indices() {
+        let looks_like = match c {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0),
+            '\u{00A0}' | '\u{2000}'..='\u{200A}' | '\u{202F}' | '\u{205F}' | '\u{3000}' => {
+                Some(' ')
+            }
+            _ => None,
+        };
+        if let Some(looks_like) = looks_like {
+            out.push(Confusable {
+                span: i..i + c.len_utf8(),
+                found: c,
+                looks_like,
+            });
+        }
+        if c.is_alphanumeric() {
+            word.push((i, c));
+        } else {
+            mixed_script_lookalikes(&mut word, &mut out);
+        }
+    }
+    mixed_script_lookalikes(&mut word, &mut out);
+    out.sort_by_key(|c| c.span.start);
+    out
+}
+
+/// Report the lookalikes of `word` if it also has ASCII letters, then clear it.
+fn mixed_script_lookalikes(word: &mut Vec<(usize, char)>, out: &mut Vec<Confusable>) {
+    if word.iter().any(|(_, c)| c.is_ascii_alphabetic()) {
+        for &(i, c) in word.iter() {
+            if let Some(&(_, ascii)) = LOOKALIKES.iter().find(|(l, _)| *l == c) {
+                out.push(Confusable {
+                    span: i..i + c.len_utf8(),
+                    found: c,
+                    looks_like: ascii,
+                });
+            }
+        }
+    }
+    word.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nfc_composes_and_strips_zero_width() {
+        let nfd = "d\u{65}\u{301}veloppeurs\u{FEFF}";
+        assert_eq!(Normalization::Nfc.apply(nfd), "développeurs");
+        assert!(matches!(
+            Normalization::Nfc.apply("développeurs"),
+            Cow::Borrowed(_)
+        ));
+        // NFC keeps non-breaking spaces and typographic apostrophes
+        assert_eq!(Normalization::Nfc.apply("d’oubli\u{A0}!"), "d’oubli\u{A0}!");
+        assert_eq!(Normalization::Nfkc.apply("d’oubli\u{A0}!"), "d’oubli !");
+        assert_eq!(Normalization::None.apply("a\u{200B}b"), "a\u{200B}b");
+    }
+
+    #[test]
+    fn confusables_only_in_mixed_words() {
+        assert!(confusables("правда développeurs d’oubli").is_empty());
+        let found = confusables("Аpache-2.0\u{A0}; ｆill");
+        let chars: Vec<(char, char)> = found.iter().map(|c| (c.found, c.looks_like)).collect();
+        assert_eq!(chars, [('А', 'A'), ('\u{A0}', ' '), ('ｆ', 'f')]);
+        assert_eq!(found[0].span, 0..2);
+    }
+
+    #[test]
+    fn apply_vars_borrows_clean_maps_and_refuses_collisions() {
+        let clean = HashMap::from([("prénom".to_string(), "Zoé".to_string())]);
+        assert!(matches!(
+            Normalization::Nfc.apply_vars(&clean),
+            Ok(Cow::Borrowed(_))
+        ));
+
+        let nfd = HashMap::from([("pre\u{301}nom".to_string(), "Zoe\u{301}".to_string())]);
+        assert_eq!(*Normalization::Nfc.apply_vars(&nfd).unwrap(), clean);
+
+        let both = HashMap::from([
+            ("prénom".to_string(), "Zoé".to_string()),
+            ("pre\u{301}nom".to_string(), "Max".to_string()),
+        ]);
+        assert_eq!(
+            Normalization::Nfc.apply_vars(&both),
+            Err(FillError::NormalizedKeyCollision(
+                "pre\u{301}nom".to_string(),
+                "prénom".to_string()
+            ))
+        );
+        // without normalisation they are two variables
+        assert!(Normalization::None.apply_vars(&both).is_ok());
+    }
+}
@@ -0,0 +1,439 @@
+//! Named templates, compiled once and shared between threads.
+//!
+//! A [`TemplateSet`](crate::registry::TemplateSet) maps names to parsed
+//! [`Template`]s. [`TemplateSet::from_dir`](crate::registry::TemplateSet::from_dir)
+//! loads every file under a directory, named after its path relative to that
+//! directory with `/` separators and without its last extension:
+//! `templates/support/reply.md` is `support/reply`, `templates/body.json.tpl`
+//! is `body.json`. A file's [front matter](crate::front_matter) is stripped
+//! from the template: its `tagged` variables apply when parsing, the rest
+//! (defaults, required and secret variables, rules) is kept by name for the
+//! caller, see [`TemplateSet::front_matter`](crate::registry::TemplateSet::front_matter).
+//!
+//! Templates of a set can use each other:
+//!
+//! - `{{> name}}` renders template `name` in place (a partial);
+//! - `{{$name}}default{{/name}}` declares a block a child may replace;
+//! - `{{< name}}{{$block}}...{{/block}}{{/name}}` renders template `name`
+//!   with the blocks listed here replacing its own (inheritance); anything else
+//!   inside the section is ignored. Along a chain of parents, the block given
+//!   by the outermost child wins.
+//!
+//! Included templates are rendered with the variables and the options of the
+//! template being rendered; nesting is bounded by
+//! [`RenderLimits::max_template_depth`](crate::RenderLimits::max_template_depth).
+//! Section tags follow the strict key grammar and are never recognised inside
+//! variable values.
+//!
+//! [`SharedTemplateSet`](crate::registry::SharedTemplateSet) holds the current
+//! set behind an `Arc` so a service can reload its templates while serving:
+//! a new set is swapped in atomically, renders already started finish with the
+//! set they began with.
+//!
+//! # Examples
+//!
+//! ```
+//! use fill_prompt::registry::TemplateSet;
+//! use fill_prompt::Template;
+//!
+//! let mut set = TemplateSet::new();
+//! set.insert("base", Template::parse("{{$intro}}Hello{{/intro}}, {{who}}.").unwrap());
+//! set.insert("polite", Template::parse("{{< base}}{{$intro}}Good morning{{/intro}}{{/base}}").unwrap());
+//! set.insert("signed", Template::parse("{{> polite}}\n-- {{me}}").unwrap());
+//! set.check().unwrap();
+//!
+//! let vars = [("who", "Alice"), ("me", "Bob")];
+//! assert_eq!(set.render("signed", &vars[..]).unwrap(), "Good morning, Alice.\n-- Bob");
+//! ```
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::escape::Escape;
+use crate::front_matter::{self, FrontMatter};
+use crate::lookup::Lookup;
+use crate::source_map::SourceMap;
+use crate::{FillError, FillOptions, Template};
+
+/// Parsed templates by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TemplateSet {
+    templates: BTreeMap<String, Template>,
+    front_matter: BTreeMap<String, FrontMatter>,
+}
+
+impl TemplateSet {
+    /// An empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load every file under `dir` (recursively, hidden entries skipped),
+    /// parsed with `options`, then [`check`](TemplateSet::check) the result.
+    /// Unless `options.escape` is set, each template escapes values for the
+    /// format of its own file name, as the CLI does, and front matters are
+    /// handled as the CLI does (see the [module docs](self)).
+    ///
+    /// # Errors
+    ///
+    /// Fails on unreadable or non UTF-8 files, invalid front matters and
+    /// templates that do not parse (the error names the template), two files mapping to the same name, and
+    /// references to templates missing from the directory.
+    pub fn from_dir(dir: impl AsRef<Path>, options: &FillOptions) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut set = TemplateSet::new();
+        set.load_dir(dir, dir, options)?;
+        set.check()
+            .with_context(|| format!("invalid template set '{}'", dir.display()))?;
+        Ok(set)
+    }
+
+    fn load_dir(&mut self, root: &Path, dir: &Path, options: &FillOptions) -> Result<()> {
+        let entries = fs::read_dir(dir)
+            .with_context(|| format!("failed to read directory '{}'", dir.display()))?;
+        let mut paths = Vec::new();
+        for entry in entries {
+            let entry =
+                entry.with_context(|| format!("failed to read directory '{}'", dir.display()))?;
+            if !entry.file_name().to_string_lossy().starts_with('.') {
+                paths.push(entry.path());
+            }
+        }
+        paths.sort();
+        for path in paths {
+            if path.is_dir() {
+                self.load_dir(root, &path, options)?;
+                continue;
+            }
+            let name = template_name(root, &path)?;
+            let source = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read template '{}'", path.display()))?;
+            let (front, body) = front_matter::parse(&source).with_context(|| {
+                format!("invalid front matter in `{}` ('{}')", name, path.display())
+            })?;
+            let mut file_options = options.clone();
+            if file_options.escape == Escape::None {
+                file_options.escape = Escape::from_path(&path);
+            }
+            if let Some(front) = &front {
+                front.apply_options(&mut file_options);
+            }
+            let template = Template::parse_with(body, &file_options).with_context(|| {
+                format!("failed to parse template `{}` ('{}')", name, path.display())
+            })?;
+            if self.templates.contains_key(&name) {
+                return Err(anyhow!(
+                    "duplicate template name `{}` ('{}')",
+                    name,
+                    path.display()
+                ));
+            }
+            if let Some(front) = front {
+                self.front_matter.insert(name.clone(), front);
+            }
+            self.templates.insert(name, template);
+        }
+        Ok(())
+    }
+
+    /// Add or replace the template `name`, returning the previous one. The
+    /// front matter loaded with the previous one, if any, is dropped.
+    pub fn insert(&mut self, name: impl Into<String>, template: Template) -> Option<Template> {
+        let name = name.into();
+        self.front_matter.remove(&name);
+        self.templates.insert(name, template)
+    }
+
+    /// The template `name`, if any.
+    pub fn get(&self, name: &str) -> Option<&Template> {
+        self.templates.get(name)
+    }
+
+    /// The front matter of the file template `name` was loaded from, if it
+    /// had one. Its defaults, required variables, secrets and rules are left
+    /// to the caller, as [`TemplateSet::render`] only takes variables.
+    pub fn front_matter(&self, name: &str) -> Option<&FrontMatter> {
+        self.front_matter.get(name)
+    }
+
+    /// Template names, sorted.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.templates.keys().map(String::as_str)
+    }
+
+    /// Number of templates.
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    /// Whether the set has no template.
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+
+    /// Make sure every template included or extended by a member of the set
+    /// is in the set.
+    ///
+    /// # Errors
+    ///
+    /// [`FillError::UnknownTemplate`] for the first missing name, in template
+    /// name order.
+    pub fn check(&self) -> Result<(), FillError> {
+        for template in self.templates.values() {
+            if let Some(missing) = template
+                .includes()
+                .into_iter()
+                .find(|name| !self.templates.contains_key(*name))
+            {
+                return Err(FillError::UnknownTemplate(missing.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Render the template `name` with `vars`, resolving its partials and
+    /// parents in this set.
+    ///
+    /// # Errors
+    ///
+    /// [`FillError::UnknownTemplate`] when `name` (or a template it uses) is
+    /// not in the set, [`FillError::IncludeTooDeep`] past
+    /// [`RenderLimits::max_template_depth`](crate::RenderLimits::max_template_depth),
+    /// and the errors of [`Template::render`].
+    pub fn render<L: Lookup + ?Sized>(&self, name: &str, vars: &L) -> Result<String, FillError> {
+        self.get(name)
+            .ok_or_else(|| FillError::UnknownTemplate(name.to_string()))?
+            .render_in(Some(self), vars)
+    }
+
+    /// [`TemplateSet::render`] with a [`SourceMap`]: literal ranges name the
+    /// partial or parent they were copied from.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`TemplateSet::render`].
+    pub fn render_with_source_map<L: Lookup + ?Sized>(
+        &self,
+        name: &str,
+        vars: &L,
+    ) -> Result<(String, SourceMap), FillError> {
+        self.get(name)
+            .ok_or_else(|| FillError::UnknownTemplate(name.to_string()))?
+            .render_mapped_in(Some(self), vars)
+    }
+}
+
+/// Name of the template at `path`: its path relative to `root`, `/`
+/// separated, without the last extension.
+fn template_name(root: &Path, path: &Path) -> Result<String> {
+    let relative = path.strip_prefix(root).unwrap_or(path).with_extension("");
+    let parts: Option<Vec<&str>> = relative.iter().map(|part| part.to_str()).collect();
+    parts
+        .map(|parts| parts.join("/"))
+        .ok_or_else(|| anyhow!("template path is not UTF-8: '{}'", path.display()))
+}
+
+/// The current [`TemplateSet`] of a service, replaceable while in use.
+#[derive(Debug, Default)]
+pub struct SharedTemplateSet {
+    current: RwLock<Arc<TemplateSet>>,
+}
+
+impl SharedTemplateSet {
+    /// Share `set`.
+    pub fn new(set: TemplateSet) -> Self {
+        SharedTemplateSet {
+            current: RwLock::new(Arc::new(set)),
+        }
+    }
+
+    /// The current set. It stays valid, unchanged, after a reload.
+    pub fn load(&self) -> Arc<TemplateSet> {
+        Arc::clone(&self.current.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Swap in `set`, returning the previous one.
+    pub fn replace(&self, set: TemplateSet) -> Arc<TemplateSet> {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        std::mem::replace(&mut *current, Arc::new(set))
+    }
+
+    /// Load `dir` again (see [`TemplateSet::from_dir`]) and swap it in. On
+    /// error the current set is kept.
+    pub fn reload_dir(&self, dir: impl AsRef<Path>, options: &FillOptions) -> Result<()> {
+        let set = TemplateSet::from_dir(dir, options)?;
+        self.replace(set);
+        Ok(())
+    }
+
+    /// [`TemplateSet::render`] with the current set.
+    pub fn render<L: Lookup + ?Sized>(&self, name: &str, vars: &L) -> Result<String, FillError> {
+        self.load().render(name, vars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RenderLimits;
+    use tempfile::TempDir;
+
+    fn write(dir: &Path, path: &str, text: &str) {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    fn parsed(source: &str) -> Template {
+        Template::parse(source).unwrap()
+    }
+
+    #[test]
+    fn is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<TemplateSet>();
+        assert_send_sync::<SharedTemplateSet>();
+    }
+
+    #[test]
+    fn loads_a_directory_under_path_names() {
+        let dir = TempDir::new().unwrap();
+        write(dir.path(), "support/reply.md", "{{> partials/signature}}");
+        write(dir.path(), "partials/signature.txt", "-- {{author}}");
+        write(dir.path(), "body.json.tpl", "{\"q\": \"{{q}}\"}");
+        write(dir.path(), "notes.md", "# {{q}}");
+        write(dir.path(), ".hidden", "{{ not a template");
+        let set = TemplateSet::from_dir(dir.path(), &FillOptions::default()).unwrap();
+        assert_eq!(
+            set.names().collect::<Vec<_>>(),
+            ["body.json", "notes", "partials/signature", "support/reply"]
+        );
+        assert_eq!(
+            set.render("support/reply", &[("author", "Max")][..])
+                .unwrap(),
+            "-- Max"
+        );
+        // escaping follows each file's extension
+        assert_eq!(
+            set.render("body.json", &[("q", "say \"hi\"")][..]).unwrap(),
+            r#"{"q": "say \"hi\""}"#
+        );
+        // a single extension does not escape
+        assert_eq!(
+            set.render("notes", &[("q", "a_b (c)")][..]).unwrap(),
+            "# a_b (c)"
+        );
+
+        write(dir.path(), "broken.txt", "{{> missing}}");
+        let err = TemplateSet::from_dir(dir.path(), &FillOptions::default()).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("unknown template `missing`"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn markdown_rules_are_not_front_matter() {
+        let dir = TempDir::new().unwrap();
+        write(dir.path(), "doc.md", "---\nRole: {{role}}\n---\nAnswer.");
+        let set = TemplateSet::from_dir(dir.path(), &FillOptions::default()).unwrap();
+        assert_eq!(set.front_matter("doc"), None);
+        assert_eq!(
+            set.render("doc", &[("role", "judge")][..]).unwrap(),
+            "---\nRole: judge\n---\nAnswer."
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn strips_and_keeps_front_matter() {
+        let dir = TempDir::new().unwrap();
+        write(
+            dir.path(),
+            "ask.txt",
+            "+++\n[variables.q]\ndefault = \"why\"\ntagged = true\n+++\nQ: {{q}}",
+        );
+        let set = TemplateSet::from_dir(dir.path(), &FillOptions::default()).unwrap();
+        let front = set.front_matter("ask").unwrap();
+        assert_eq!(front.variables["q"].default.as_deref(), Some("why"));
+        assert_eq!(
+            set.render("ask", &[("q", "how")][..]).unwrap(),
+            "Q: <q>how</q>"
+        );
+
+        write(dir.path(), "bad.txt", "---\nvariables: [\n---\n");
+        let err = TemplateSet::from_dir(dir.path(), &FillOptions::default()).unwrap_err();
+        assert!(format!("{err:#}").contains("`bad`"), "{err:#}");
+    }
+
+    #[test]
+    fn outermost_block_wins_along_the_chain() {
+        let mut set = TemplateSet::new();
+        set.insert(
+            "base",
+            parsed("<{{$a}}A{{/a}}|{{$b}}B{{/b}}|{{$c}}C{{/c}}>"),
+        );
+        set.insert(
+            "mid",
+            parsed("{{< base}}{{$a}}mid-a{{/a}}{{$b}}mid-b{{/b}}{{/base}}"),
+        );
+        set.insert(
+            "leaf",
+            parsed("{{< mid}}ignored {{$a}}leaf-{{x}}{{/a}}{{/mid}}"),
+        );
+        set.check().unwrap();
+        let vars = [("x", "1")];
+        assert_eq!(set.render("base", &vars[..]).unwrap(), "<A|B|C>");
+        assert_eq!(set.render("mid", &vars[..]).unwrap(), "<mid-a|mid-b|C>");
+        assert_eq!(set.render("leaf", &vars[..]).unwrap(), "<leaf-1|mid-b|C>");
+    }
+
+    #[test]
+    fn recursion_and_unknown_names_are_errors() {
+        let mut set = TemplateSet::new();
+        set.insert("loop", parsed("x{{> loop}}"));
+        assert_eq!(
+            set.render("loop", &[("k", "v")][..]),
+            Err(FillError::IncludeTooDeep(
+                "loop".to_string(),
+                RenderLimits::default().max_template_depth
+            ))
+        );
+        assert_eq!(
+            set.render("nope", &[("k", "v")][..]),
+            Err(FillError::UnknownTemplate("nope".to_string()))
+        );
+        // on its own, a template has nothing to include
+        assert_eq!(
+            parsed("{{> loop}}").render_lookup(&[("k", "v")][..]),
+            Err(FillError::UnknownTemplate("loop".to_string()))
+        );
+    }
+
+    #[test]
+    fn reload_swaps_atomically() {
+        let dir = TempDir::new().unwrap();
+        write(dir.path(), "hello.txt", "Hello {{who}}");
+        let shared = SharedTemplateSet::new(
+            TemplateSet::from_dir(dir.path(), &FillOptions::default()).unwrap(),
+        );
+        let before = shared.load();
+
+        write(dir.path(), "hello.txt", "Bonjour {{who}}");
+        shared
+            .reload_dir(dir.path(), &FillOptions::default())
+            .unwrap();
+        let vars = [("who", "Max")];
+        assert_eq!(shared.render("hello", &vars[..]).unwrap(), "Bonjour Max");
+        assert_eq!(before.render("hello", &vars[..]).unwrap(), "Hello Max");
+
+        write(dir.path(), "hello.txt", "{{ broken");
+        assert!(shared
+            .reload_dir(dir.path(), &FillOptions::default())
+            .is_err());
+        assert_eq!(shared.render("hello", &vars[..]).unwrap(), "Bonjour Max");
+    }
+}
@@ -0,0 +1,185 @@
+//! Secret variables: values (tokens, internal URLs...) that must never be
+//! echoed outside the rendered output.
+//!
+//! A variable is secret when its key carries the
+//! [`SECRET_PREFIX`](crate::secret::SECRET_PREFIX) (`secret:api_token`), when
+//! it comes from the `secrets` table of a vars file (see [`vars::SECRETS_KEY`](crate::vars::SECRETS_KEY)), or when the
+//! caller says so (`--secret-var` in the CLI). Only the names of secret
+//! variables are tracked; the values stay in the ordinary variables map so the
+//! renderer and the diagnostics need no special case. None of the library
+//! errors or reports contain values; the helpers below cover the places that
+//! could (`Debug` output, excerpts) and wipe the values once rendering is done.
+//! [`ZeroizingVars`](crate::secret::ZeroizingVars) wipes a whole map when it is dropped or replaced, so
+//! early returns do not leave values behind.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::collections::{BTreeSet, HashMap};
+//! use fill_prompt::secret::{split_secret_key, zeroize_secrets};
+//!
+//! assert_eq!(split_secret_key("secret:api_token"), ("api_token", true));
+//! assert_eq!(split_secret_key("author"), ("author", false));
+//!
+//! let mut vars = HashMap::from([
+//!     ("api_token".to_string(), "tok-123".to_string()),
+//!     ("author".to_string(), "Max".to_string()),
+//! ]);
+//! zeroize_secrets(&mut vars, &BTreeSet::from(["api_token".to_string()]));
+//! assert!(!vars.contains_key("api_token"));
+//! assert_eq!(vars["author"], "Max");
+//! ```
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+use zeroize::Zeroize;
+
+use crate::Lookup;
+
+/// Key prefix marking a variable as secret (`secret:api_token`).
+pub const SECRET_PREFIX: &str = "secret:";
+
+/// Shown in place of a secret value.
+pub const REDACTED: &str = "***";
+
+/// Split the [`SECRET_PREFIX`] off `key`: `(name, is_secret)`.
+pub fn split_secret_key(key: &str) -> (&str, bool) {
+    match key.strip_prefix(SECRET_PREFIX) {
+        Some(name) => (name, true),
+        None => (key, false),
+    }
+}
+
+/// Overwrite the values of the `secrets` variables with zeros, then remove
+/// them from `vars`.
+///
+/// This is best effort: copies made earlier (by the caller, a parser, or the
+/// rendered output itself) are not reached.
+pub fn zeroize_secrets(vars: &mut HashMap<String, String>, secrets: &BTreeSet<String>) {
+    for name in secrets {
+        if let Some(mut value) = vars.remove(name) {
+            value.zeroize();
+        }
+    }
+}
+
+/// A variables map whose values are overwritten with zeros when it is
+/// dropped.
+///
+/// Every value is wiped, not only the secret ones: the set of secret names
+/// can change on the way (normalisation) and a missed value costs more than
+/// a few extra writes. Assigning a new map to a `ZeroizingVars` drops, and so
+/// wipes, the previous one. It derefs to the inner `HashMap`.
+///
+/// ```
+/// use fill_prompt::secret::ZeroizingVars;
+///
+/// let mut vars = ZeroizingVars::default();
+/// vars.insert("api_token".to_string(), "tok-123".to_string());
+/// assert_eq!(fill_prompt::render_with("{{api_token}}", &vars).unwrap(), "tok-123");
+/// ```
+#[derive(Default)]
+pub struct ZeroizingVars(pub HashMap<String, String>);
+
+impl Deref for ZeroizingVars {
+    type Target = HashMap<String, String>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for ZeroizingVars {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<HashMap<String, String>> for ZeroizingVars {
+    fn from(vars: HashMap<String, String>) -> Self {
+        ZeroizingVars(vars)
+    }
+}
+
+impl Lookup for ZeroizingVars {
+    fn lookup(&self, key: &str) -> Option<&str> {
+        self.0.lookup(key)
+    }
+}
+
+/// Wipes the values, keeping the (empty) entries.
+impl Zeroize for ZeroizingVars {
+    fn zeroize(&mut self) {
+        for value in self.0.values_mut() {
+            value.zeroize();
+        }
+    }
+}
+
+impl Drop for ZeroizingVars {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+/// `Debug` view of a variables map with the secret values replaced by
+/// [`REDACTED`], entries sorted by name.
+pub struct RedactedVars<'a> {
+    /// All variables.
+    pub vars: &'a HashMap<String, String>,
+    /// Names of the secret ones.
+    pub secrets: &'a BTreeSet<String>,
+}
+
+impl fmt::Debug for RedactedVars<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut entries: Vec<(&String, &str)> = self
+            .vars
+            .iter()
+            .map(|(k, v)| {
+                let shown = if self.secrets.contains(k) {
+                    REDACTED
+                } else {
+                    v.as_str()
+                };
+                (k, shown)
+            })
+            .collect();
+        entries.sort();
+        f.debug_map().entries(entries).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_hides_secret_values() {
+        let vars = HashMap::from([
+            ("token".to_string(), "tok-123".to_string()),
+            ("who".to_string(), "Max".to_string()),
+        ]);
+        let secrets = BTreeSet::from(["token".to_string()]);
+        let shown = format!(
+            "{:?}",
+            RedactedVars {
+                vars: &vars,
+                secrets: &secrets
+            }
+        );
+        assert_eq!(shown, r#"{"token": "***", "who": "Max"}"#);
+    }
+
+    #[test]
+    fn zeroizing_vars_wipes_every_value() {
+        let mut vars = ZeroizingVars::from(HashMap::from([
+            ("token".to_string(), "tok-123".to_string()),
+            ("who".to_string(), "Max".to_string()),
+        ]));
+        vars.zeroize();
+        assert_eq!(vars["token"], "");
+        assert_eq!(vars["who"], "");
+    }
+}
@@ -0,0 +1,202 @@
+//! Source maps: where each byte of a rendered prompt came from.
+//!
+//! [`Template::render_with_source_map`](crate::Template::render_with_source_map)
+//! (or [`TemplateSet::render_with_source_map`](crate::registry::TemplateSet::render_with_source_map))
+//! returns, with the output, a [`SourceMap`](crate::source_map::SourceMap):
+//! the output split into consecutive ranges, each tagged with the template
+//! text it was copied from or the variable whose value it holds. Ranges and
+//! spans are byte offsets. Decorations (escaping, fences, tags, indentation)
+//! belong to the value they wrap; a value expanded with
+//! [`FillOptions::expand_values`](crate::FillOptions::expand_values) is one
+//! range of the outer variable.
+//!
+//! # Examples
+//!
+//! ```
+//! use fill_prompt::source_map::Origin;
+//! use fill_prompt::Template;
+//!
+//! let tpl = Template::parse("Summarise: {{text}}").unwrap();
+//! let (output, map) = tpl.render_with_source_map(&[("text", "a \"b\"")][..]).unwrap();
+//! assert_eq!(output, "Summarise: a \"b\"");
+//! assert_eq!(
+//!     map.mappings()[0].origin,
+//!     Origin::Literal { template: None, span: 0..11 }
+//! );
+//! assert_eq!(map.mappings()[1].output, 11..16);
+//! assert_eq!(
+//!     map.to_json(),
+//!     r#"{"mappings":[{"output":[0,11],"kind":"literal","template":null,"span":[0,11]},{"output":[11,16],"kind":"variable","key":"text","template":null,"span":[11,19]}]}"#
+//! );
+//! ```
+use std::ops::Range;
+
+use crate::escape::Escape;
+
+/// What a range of the output was made from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    /// Text copied from a template.
+    Literal {
+        /// Name in the set of the partial or parent template it belongs to,
+        /// `None` for the template rendered.
+        template: Option<String>,
+        /// Byte range of the text in that template.
+        span: Range<usize>,
+    },
+    /// The value of a variable, decorations included.
+    Variable {
+        /// The variable name.
+        key: String,
+        /// Template of the placeholder, as for [`Origin::Literal`].
+        template: Option<String>,
+        /// Byte range of the placeholder, braces included.
+        span: Range<usize>,
+    },
+}
+
+/// A range of the output and its origin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    /// Byte range in the output.
+    pub output: Range<usize>,
+    /// Where it came from.
+    pub origin: Origin,
+}
+
+/// Origins of a rendered output, in output order; the ranges follow each
+/// other without gaps.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    mappings: Vec<Mapping>,
+}
+
+impl SourceMap {
+    pub(crate) fn new(mappings: Vec<Mapping>) -> Self {
+        SourceMap { mappings }
+    }
+
+    /// All mappings, in output order. Empty values have an empty range.
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    /// The mapping covering the output byte at `offset`, if any.
+    pub fn at(&self, offset: usize) -> Option<&Mapping> {
+        let i = self.mappings.partition_point(|m| m.output.end <= offset);
+        self.mappings.get(i).filter(|m| m.output.start <= offset)
+    }
+
+    /// The map as JSON: `{"mappings":[{"output":[start,end],"kind":...}]}`,
+    /// `kind` being `literal` or `variable` (which adds `key`), then
+    /// `template` (name or `null`) and `span`.
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"mappings\":[");
+        for (i, m) in self.mappings.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let (template, span) = match &m.origin {
+                Origin::Literal { template, span } => {
+                    out.push_str(&format!(
+                        "{{\"output\":[{},{}],\"kind\":\"literal\"",
+                        m.output.start, m.output.end
+                    ));
+                    (template, span)
+                }
+                Origin::Variable {
+                    key,
+                    template,
+                    span,
+                } => {
+                    out.push_str(&format!(
+                        "{{\"output\":[{},{}],\"kind\":\"variable\",\"key\":\"",
+                        m.output.start, m.output.end
+                    ));
+                    Escape::Json.push(&mut out, key);
+                    out.push('"');
+                    (template, span)
+                }
+            };
+            match template {
+                Some(name) => {
+                    out.push_str(",\"template\":\"");
+                    Escape::Json.push(&mut out, name);
+                    out.push('"');
+                }
+                None => out.push_str(",\"template\":null"),
+            }
+            out.push_str(&format!(",\"span\":[{},{}]}}", span.start, span.end));
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::registry::TemplateSet;
+    use crate::{FillOptions, Template};
+
+    use super::*;
+
+    #[test]
+    fn ranges_cover_the_output_and_name_their_template() {
+        let options = FillOptions {
+            indent: true,
+            expand_values: true,
+            ..FillOptions::default()
+        };
+        let mut set = TemplateSet::new();
+        let parse = |s: &str| Template::parse_with(s, &options).unwrap();
+        set.insert("base", parse("[{{$body}}none{{/body}}] {{> sig}}"));
+        set.insert("sig", parse("-- {{who}}"));
+        set.insert(
+            "page",
+            parse("{{< base}}{{$body}}- {{list}}{{/body}}{{/base}}"),
+        );
+        let vars = [("who", "{{name}}"), ("name", "Ann"), ("list", "a\nb")];
+
+        let (output, map) = set.render_with_source_map("page", &vars[..]).unwrap();
+        assert_eq!(output, "[- a\n   b] -- Ann");
+        let mut end = 0;
+        for m in map.mappings() {
+            assert_eq!(m.output.start, end);
+            end = m.output.end;
+        }
+        assert_eq!(end, output.len());
+
+        let origin = |offset| map.at(offset).map(|m| m.origin.clone());
+        let literal = |name: &str, span| Origin::Literal {
+            template: Some(name.to_string()),
+            span,
+        };
+        assert_eq!(origin(0), Some(literal("base", 0..1)));
+        assert_eq!(
+            origin(1),
+            Some(Origin::Literal {
+                template: None,
+                span: 19..21
+            })
+        );
+        assert_eq!(
+            origin(6),
+            Some(Origin::Variable {
+                key: "list".to_string(),
+                template: None,
+                span: 21..29,
+            })
+        );
+        assert_eq!(origin(10), Some(literal("base", 23..25)));
+        assert_eq!(origin(11), Some(literal("sig", 0..3)));
+        assert_eq!(
+            origin(14),
+            Some(Origin::Variable {
+                key: "who".to_string(),
+                template: Some("sig".to_string()),
+                span: 3..10,
+            })
+        );
+        assert_eq!(origin(output.len()), None);
+    }
+}
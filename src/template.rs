@@ -0,0 +1,1930 @@
+//! Compiled templates: the `{{key}}` parser and the renderer built on it.
+//!
+//! [`fill_template`](crate::fill_template) parses and renders in one call; use
+//! [`Template`] directly to parse once and render many times, or to inspect the
+//! placeholders of a template before filling it.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::collections::HashMap;
+//! use fill_prompt::Template;
+//!
+//! let tpl = Template::parse("Hi {{who}}, welcome to {{place}}.").unwrap();
+//! assert_eq!(tpl.keys().into_iter().collect::<Vec<_>>(), ["place", "who"]);
+//!
+//! let mut vars = HashMap::new();
+//! vars.insert("who".to_string(), "Alice".to_string());
+//! vars.insert("place".to_string(), "Rust".to_string());
+//! assert_eq!(tpl.render(&vars).unwrap(), "Hi Alice, welcome to Rust.");
+//! ```
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+
+use zeroize::Zeroize;
+
+use crate::escape::{fenced, tagged, Escape};
+use crate::extract::{ExtractError, Piece};
+use crate::live::Slot;
+use crate::lookup::Lookup;
+use crate::normalize::Normalization;
+use crate::registry::TemplateSet;
+use crate::source_map::{Mapping, Origin, SourceMap};
+use crate::{FillError, FillOptions, KeySyntax, ValuePolicy};
+
+#[cfg(feature = "parallel")]
+use rayon::iter::ParallelIterator;
+
+/// Transformation attached to a placeholder with `{{key | name(args)}}`.
+///
+/// Filters are only recognised with [`KeySyntax::Strict`]; the permissive
+/// grammar treats `|` as part of the key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Filter {
+    /// `default("text")`: value used when the variable is not provided.
+    Default(String),
+    /// `json`, `yaml`, `shell`, `xml`, `markdown` or `raw`: output escaping,
+    /// overriding [`FillOptions::escape`] for this placeholder.
+    Escape(Escape),
+    /// `indent` / `noindent`: re-indent continuation lines of multi-line
+    /// values, overriding [`FillOptions::indent`] for this placeholder.
+    Indent(bool),
+    /// `fence` / `fence("lang")`: wrap the value in a Markdown code fence
+    /// longer than any backtick run it contains. The engine-wide
+    /// [`FillOptions::escape`] does not apply to fenced values.
+    Fence(String),
+    /// `tagged` / `untagged`: wrap the value in `<key>…</key>`, overriding
+    /// [`FillOptions::tag_all`] and [`FillOptions::tagged`] for this placeholder.
+    Tagged(bool),
+}
+
+/// A `{{key}}` occurrence in a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placeholder {
+    key: String,
+    span: Range<usize>,
+    filters: Vec<Filter>,
+}
+
+impl Placeholder {
+    /// Variable name, without the surrounding whitespace.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Byte range of the whole placeholder (`{{` through `}}`) in the template.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// Filters in the order they are written.
+    pub fn filters(&self) -> &[Filter] {
+        &self.filters
+    }
+
+    /// Fallback declared with `| default("...")`, if any.
+    pub fn default_value(&self) -> Option<&str> {
+        self.filters.iter().find_map(|f| match f {
+            Filter::Default(v) => Some(v.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Indentation requested by a filter; the last one written wins.
+    pub fn indent(&self) -> Option<bool> {
+        self.filters.iter().rev().find_map(|f| match f {
+            Filter::Indent(on) => Some(*on),
+            _ => None,
+        })
+    }
+
+    /// Tag wrapping requested by a filter; the last one written wins.
+    pub fn tagged(&self) -> Option<bool> {
+        self.filters.iter().rev().find_map(|f| match f {
+            Filter::Tagged(on) => Some(*on),
+            _ => None,
+        })
+    }
+
+    /// Language tag of a `fence(...)` filter (empty for a bare `fence`).
+    pub fn fence(&self) -> Option<&str> {
+        self.filters.iter().rev().find_map(|f| match f {
+            Filter::Fence(info) => Some(info.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Escaping requested by a filter; the last one written wins.
+    pub fn escape(&self) -> Option<Escape> {
+        self.filters.iter().rev().find_map(|f| match f {
+            Filter::Escape(e) => Some(*e),
+            _ => None,
+        })
+    }
+
+    /// The decorations of this placeholder's value under `options`.
+    pub(crate) fn decorations(&self, options: &FillOptions) -> Decorations<'_> {
+        let fence = self.fence();
+        Decorations {
+            escape: self.escape().unwrap_or(match fence {
+                Some(_) => Escape::None,
+                None => options.escape,
+            }),
+            fence,
+            tag: self
+                .tagged()
+                .unwrap_or_else(|| options.tag_all || options.tagged.contains(self.key())),
+            indent: self.indent().unwrap_or(options.indent),
+        }
+    }
+}
+
+/// How a value is decorated where it lands, applied in field order.
+pub(crate) struct Decorations<'p> {
+    pub(crate) escape: Escape,
+    pub(crate) fence: Option<&'p str>,
+    pub(crate) tag: bool,
+    /// Continuation lines get the prefix of the line the value starts on.
+    pub(crate) indent: bool,
+}
+
+/// What a render did with the variables it was given; the same analysis as
+/// [`check_variables`](crate::diagnostics::check_variables).
+///
+/// Names are sorted, which keeps reports stable across runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenderReport {
+    /// Provided variables referenced by at least one placeholder.
+    pub used: BTreeSet<String>,
+    /// Provided variables no placeholder refers to (often a typo in a key).
+    pub unused: BTreeSet<String>,
+    /// Variables not provided, filled from a `default(...)` filter instead.
+    pub defaulted: BTreeSet<String>,
+}
+
+/// Output of [`Template::render_with_report`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rendered {
+    /// The filled template.
+    pub output: String,
+    /// How the variables were consumed.
+    pub report: RenderReport,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(Range<usize>),
+    Placeholder(Placeholder),
+    /// `{{> name}}`: another template of the set, rendered in place.
+    Partial(String),
+    /// `{{$name}}...{{/name}}`: default content a child template may replace.
+    Block(String, Vec<Segment>),
+    /// `{{< name}}...{{/name}}`: template `name`, rendered with the blocks
+    /// listed here (only [`Segment::Block`]s are kept) replacing its own.
+    Parent(String, Vec<Segment>),
+}
+
+/// Call `f` on every placeholder of `segments`, sections included, in order.
+fn walk_placeholders<'s>(segments: &'s [Segment], f: &mut impl FnMut(&'s Placeholder)) {
+    for segment in segments {
+        match segment {
+            Segment::Placeholder(p) => f(p),
+            Segment::Block(_, body) | Segment::Parent(_, body) => walk_placeholders(body, f),
+            Segment::Literal(_) | Segment::Partial(_) => {}
+        }
+    }
+}
+
+/// Add the names of the templates `segments` include or extend to `names`.
+fn walk_includes<'s>(segments: &'s [Segment], names: &mut BTreeSet<&'s str>) {
+    for segment in segments {
+        match segment {
+            Segment::Partial(name) => {
+                names.insert(name);
+            }
+            Segment::Parent(name, body) => {
+                names.insert(name);
+                walk_includes(body, names);
+            }
+            Segment::Block(_, body) => walk_includes(body, names),
+            Segment::Literal(_) | Segment::Placeholder(_) => {}
+        }
+    }
+}
+
+/// A parsed template, ready to be rendered any number of times.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+    /// Total length of the literal segments, for sizing the output.
+    literal_len: usize,
+    options: FillOptions,
+}
+
+impl Template {
+    /// Parse `source` with the default options (strict key grammar).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FillError::UnclosedPlaceholder`], [`FillError::EmptyPlaceholder`]
+    /// or [`FillError::InvalidPlaceholderName`] when the template is malformed.
+    pub fn parse(source: &str) -> Result<Self, FillError> {
+        Self::parse_with(source, &FillOptions::default())
+    }
+
+    /// Parse `source` with explicit options.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Template::parse`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fill_prompt::{FillError, FillOptions, KeySyntax, Template};
+    ///
+    /// let err = Template::parse("{{crate name}}").unwrap_err();
+    /// assert_eq!(err, FillError::InvalidPlaceholderName("crate name".into(), 2..12));
+    ///
+    /// let opts = FillOptions { key_syntax: KeySyntax::Permissive, ..Default::default() };
+    /// let tpl = Template::parse_with("{{crate name}}", &opts).unwrap();
+    /// assert!(tpl.keys().contains("crate name"));
+    /// ```
+    pub fn parse_with(source: &str, options: &FillOptions) -> Result<Self, FillError> {
+        let source = options.normalize.apply(source);
+        let segments = parse_segments(&source, options, true)?;
+        let literal_len = segments
+            .iter()
+            .map(|s| match s {
+                Segment::Literal(r) => r.len(),
+                _ => 0,
+            })
+            .sum();
+        Ok(Template {
+            source: source.into_owned(),
+            segments,
+            literal_len,
+            options: options.clone(),
+        })
+    }
+
+    /// The template text, after [`FillOptions::normalize`]; spans refer to it.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The options the template was parsed with; rendering uses them too.
+    pub fn options(&self) -> &FillOptions {
+        &self.options
+    }
+
+    /// Every placeholder occurrence, in template order, including those
+    /// inside `{{$block}}` sections. Placeholders of included or extended
+    /// templates are not listed.
+    pub fn placeholders(&self) -> impl Iterator<Item = &Placeholder> {
+        let mut all = Vec::new();
+        walk_placeholders(&self.segments, &mut |p| all.push(p));
+        all.into_iter()
+    }
+
+    /// Names of the templates this one includes (`{{> name}}`) or extends
+    /// (`{{< name}}`), sorted. They are resolved by a [`TemplateSet`].
+    pub fn includes(&self) -> BTreeSet<&str> {
+        let mut names = BTreeSet::new();
+        walk_includes(&self.segments, &mut names);
+        names
+    }
+
+    /// The distinct variable names used by the template, sorted.
+    pub fn keys(&self) -> BTreeSet<&str> {
+        self.placeholders().map(Placeholder::key).collect()
+    }
+
+    /// The variable names that must be provided: those with at least one
+    /// occurrence lacking a `default(...)` filter.
+    pub fn required_keys(&self) -> BTreeSet<&str> {
+        self.placeholders()
+            .filter(|p| p.default_value().is_none())
+            .map(Placeholder::key)
+            .collect()
+    }
+
+    /// Render the template with `vars`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(FillError::MissingVariable(_))` for the first placeholder
+    /// that has neither a mapping nor a default, and
+    /// `Err(FillError::NormalizedKeyCollision(..))` when two names are the
+    /// same once normalised.
+    pub fn render(&self, vars: &HashMap<String, String>) -> Result<String, FillError> {
+        let vars = self.options.normalize.apply_vars(vars)?;
+        self.render_normalized(vars.as_ref())
+    }
+
+    /// Render the template with variables borrowed from any [`Lookup`]
+    /// source, without copying them.
+    ///
+    /// With [`FillOptions::normalize`](crate::FillOptions::normalize), names
+    /// are looked up in their normalised form and values are normalised as
+    /// they are inserted. A source is never listed, so two spellings of the
+    /// same name are not detected: the one looked up wins.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Template::render`], except for collisions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::BTreeMap;
+    /// use fill_prompt::Template;
+    ///
+    /// let tpl = Template::parse("{{greeting}}, {{who}}!").unwrap();
+    /// let vars = BTreeMap::from([("greeting", "Hello"), ("who", "world")]);
+    /// assert_eq!(tpl.render_lookup(&vars).unwrap(), "Hello, world!");
+    /// ```
+    pub fn render_lookup<L: Lookup + ?Sized>(&self, vars: &L) -> Result<String, FillError> {
+        self.render_in(None, vars)
+    }
+
+    /// [`Template::render_lookup`], resolving partials and parents in `templates`.
+    pub(crate) fn render_in<L: Lookup + ?Sized>(
+        &self,
+        templates: Option<&TemplateSet>,
+        vars: &L,
+    ) -> Result<String, FillError> {
+        let normalize_values = self.options.normalize != Normalization::None;
+        let mut renderer = Renderer::new(vars, &self.options, normalize_values);
+        renderer.templates = templates;
+        let mut out = String::with_capacity(self.output_len(vars));
+        renderer.write_segments(&self.source, &self.segments, &mut out)?;
+        Ok(out)
+    }
+
+    /// Render the template like [`Template::render_lookup`] and map every
+    /// byte of the output back to the template text or the variable it came
+    /// from.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Template::render`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fill_prompt::source_map::Origin;
+    /// use fill_prompt::Template;
+    ///
+    /// let tpl = Template::parse("Hi {{who}}!").unwrap();
+    /// let (output, map) = tpl.render_with_source_map(&[("who", "Ann")][..]).unwrap();
+    /// assert_eq!(output, "Hi Ann!");
+    /// let at = map.at(4).unwrap();
+    /// assert_eq!(at.output, 3..6);
+    /// assert!(matches!(&at.origin, Origin::Variable { key, .. } if key == "who"));
+    /// ```
+    pub fn render_with_source_map<L: Lookup + ?Sized>(
+        &self,
+        vars: &L,
+    ) -> Result<(String, SourceMap), FillError> {
+        self.render_mapped_in(None, vars)
+    }
+
+    /// [`Template::render_with_source_map`], resolving partials and parents
+    /// in `templates`.
+    pub(crate) fn render_mapped_in<L: Lookup + ?Sized>(
+        &self,
+        templates: Option<&TemplateSet>,
+        vars: &L,
+    ) -> Result<(String, SourceMap), FillError> {
+        let normalize_values = self.options.normalize != Normalization::None;
+        let mut renderer = Renderer::new(vars, &self.options, normalize_values);
+        renderer.templates = templates;
+        renderer.mappings = Some(Vec::new());
+        let mut out = String::with_capacity(self.output_len(vars));
+        renderer.write_segments(&self.source, &self.segments, &mut out)?;
+        let mappings = renderer.mappings.take().unwrap_or_default();
+        Ok((out, SourceMap::new(mappings)))
+    }
+
+    /// Render the template once per row of variables, on rayon's thread pool
+    /// (feature `parallel`).
+    ///
+    /// Results come back in row order; a row that fails gets its own
+    /// [`FillError`] and the other rows are still rendered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use fill_prompt::{FillError, Template};
+    ///
+    /// let tpl = Template::parse("Translate: {{text}}").unwrap();
+    /// let rows = vec![vec![("text", "bonjour")], vec![], vec![("text", "merci")]];
+    /// let results = tpl.render_batch(rows.par_iter().map(Vec::as_slice));
+    /// assert_eq!(results[0].as_deref(), Ok("Translate: bonjour"));
+    /// assert_eq!(results[1], Err(FillError::MissingVariable("text".into())));
+    /// assert_eq!(results[2].as_deref(), Ok("Translate: merci"));
+    /// ```
+    #[cfg(feature = "parallel")]
+    pub fn render_batch<I>(&self, rows: I) -> Vec<Result<String, FillError>>
+    where
+        I: ParallelIterator,
+        I::Item: Lookup,
+    {
+        rows.map(|row| self.render_lookup(&row)).collect()
+    }
+
+    /// [`Template::render_lookup`], also returning where each value landed.
+    pub(crate) fn render_tracked<L: Lookup + ?Sized>(
+        &self,
+        vars: &L,
+    ) -> Result<(String, Vec<Slot>), FillError> {
+        let normalize_values = self.options.normalize != Normalization::None;
+        let mut renderer = Renderer::new(vars, &self.options, normalize_values);
+        renderer.slots = Some(Vec::new());
+        let mut out = String::with_capacity(self.output_len(vars));
+        renderer.write_segments(&self.source, &self.segments, &mut out)?;
+        Ok((out, renderer.slots.take().unwrap_or_default()))
+    }
+
+    /// Append the decorated value of `p` to `out`, which holds the output
+    /// line it continues (for indentation).
+    pub(crate) fn render_placeholder<L: Lookup + ?Sized>(
+        &self,
+        vars: &L,
+        p: &Placeholder,
+        out: &mut String,
+    ) -> Result<(), FillError> {
+        let normalize_values = self.options.normalize != Normalization::None;
+        Renderer::new(vars, &self.options, normalize_values).write_placeholder(p, out)
+    }
+
+    /// Output size of a plain substitution: exact unless escaping or
+    /// decorations make it grow.
+    fn output_len<L: Lookup + ?Sized>(&self, vars: &L) -> usize {
+        let mut len = self.literal_len;
+        walk_placeholders(&self.segments, &mut |p| {
+            len += vars
+                .lookup(p.key())
+                .or(p.default_value())
+                .map_or(0, str::len);
+        });
+        len
+    }
+
+    /// Recover the variables `rendered` was made from, which must be an output
+    /// of this template (see [`crate::extract`]).
+    ///
+    /// # Errors
+    ///
+    /// [`ExtractError`] when the template has sections or escaped values, or
+    /// when `rendered` does not match it in exactly one way.
+    pub fn extract(&self, rendered: &str) -> Result<HashMap<String, String>, ExtractError> {
+        let mut pieces = Vec::with_capacity(self.segments.len());
+        for segment in &self.segments {
+            pieces.push(match segment {
+                Segment::Literal(r) => Piece::Literal(&self.source[r.clone()]),
+                Segment::Placeholder(p) => Piece::Value(p),
+                Segment::Partial(name) | Segment::Block(name, _) | Segment::Parent(name, _) => {
+                    return Err(ExtractError::Section(name.clone()))
+                }
+            });
+        }
+        crate::extract::extract(self, &pieces, rendered)
+    }
+
+    /// Same as [`Template::render`], but borrows the template text instead of
+    /// copying it when there is no placeholder to fill.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::borrow::Cow;
+    /// use std::collections::HashMap;
+    /// use fill_prompt::Template;
+    ///
+    /// let tpl = Template::parse("No placeholder here.").unwrap();
+    /// assert!(matches!(tpl.render_cow(&HashMap::new()), Ok(Cow::Borrowed(_))));
+    /// ```
+    pub fn render_cow(&self, vars: &HashMap<String, String>) -> Result<Cow<'_, str>, FillError> {
+        if self
+            .segments
+            .iter()
+            .any(|s| !matches!(s, Segment::Literal(_)))
+        {
+            return self.render(vars).map(Cow::Owned);
+        }
+        let max = self.options.limits.max_output_bytes;
+        if self.source.len() > max {
+            return Err(FillError::OutputTooLarge(max));
+        }
+        Ok(Cow::Borrowed(&self.source))
+    }
+
+    /// Render the template and report which variables were used, unused or
+    /// replaced by their default.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Template::render`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use fill_prompt::Template;
+    ///
+    /// let tpl = Template::parse(r#"{{name}} ({{lang | default("fr")}})"#).unwrap();
+    /// let mut vars = HashMap::new();
+    /// vars.insert("name".to_string(), "fill_prompt".to_string());
+    /// vars.insert("license".to_string(), "MIT".to_string());
+    ///
+    /// let rendered = tpl.render_with_report(&vars).unwrap();
+    /// assert_eq!(rendered.output, "fill_prompt (fr)");
+    /// assert!(rendered.report.unused.contains("license"));
+    /// assert!(rendered.report.defaulted.contains("lang"));
+    /// ```
+    pub fn render_with_report(
+        &self,
+        vars: &HashMap<String, String>,
+    ) -> Result<Rendered, FillError> {
+        let vars = self.options.normalize.apply_vars(vars)?;
+        let vars = vars.as_ref();
+        let output = self.render_normalized(vars)?;
+        // the same walk as `check_variables`: a successful render used
+        // exactly the keys it reaches
+        let (report, _) = crate::diagnostics::usage(self, vars);
+        Ok(Rendered { output, report })
+    }
+
+    /// [`Template::render`] with `vars` already normalised.
+    fn render_normalized(&self, vars: &HashMap<String, String>) -> Result<String, FillError> {
+        let mut renderer = Renderer::new(vars, &self.options, false);
+        let mut out = String::with_capacity(self.output_len(vars));
+        renderer.write_segments(&self.source, &self.segments, &mut out)?;
+        Ok(out)
+    }
+}
+
+/// Rendering state shared by the template and, with
+/// [`FillOptions::expand_values`], by the variable values it expands.
+struct Renderer<'a, L: ?Sized> {
+    vars: &'a L,
+    options: &'a FillOptions,
+    /// Normalise values as they are looked up (sources that could not be
+    /// normalised up front).
+    normalize_values: bool,
+    /// Keys whose value is being expanded, outermost first.
+    stack: Vec<String>,
+    /// Values already expanded, by key.
+    expanded: HashMap<String, String>,
+    /// Substitutions performed so far, for [`RenderLimits::max_substitutions`](crate::RenderLimits).
+    substitutions: usize,
+    /// Where `{{> name}}` and `{{< name}}` are looked up.
+    templates: Option<&'a TemplateSet>,
+    /// Partials, parents and block overrides being rendered, for
+    /// [`RenderLimits::max_template_depth`](crate::RenderLimits).
+    includes: usize,
+    /// Block overrides in effect, outermost first.
+    overrides: Vec<Override<'a>>,
+    /// Name in the set of the template being written, `None` for the one
+    /// rendered.
+    template_name: Option<&'a str>,
+    /// Where each decorated value landed, when tracked for [`LiveRender`](crate::live::LiveRender).
+    slots: Option<Vec<Slot>>,
+    /// Origin of every output range, when a [`SourceMap`] is requested.
+    mappings: Option<Vec<Mapping>>,
+}
+
+impl<L: ?Sized> Drop for Renderer<'_, L> {
+    /// Expanded values may hold secrets.
+    fn drop(&mut self) {
+        for value in self.expanded.values_mut() {
+            value.zeroize();
+        }
+    }
+}
+
+/// A `{{$block}}` body given by a child template.
+struct Override<'a> {
+    block: &'a str,
+    /// The child template: its name and text.
+    template_name: Option<&'a str>,
+    source: &'a str,
+    body: &'a [Segment],
+}
+
+impl<'a, L: Lookup + ?Sized> Renderer<'a, L> {
+    fn new(vars: &'a L, options: &'a FillOptions, normalize_values: bool) -> Self {
+        Renderer {
+            vars,
+            options,
+            normalize_values,
+            stack: Vec::new(),
+            expanded: HashMap::new(),
+            substitutions: 0,
+            templates: None,
+            includes: 0,
+            overrides: Vec::new(),
+            template_name: None,
+            slots: None,
+            mappings: None,
+        }
+    }
+
+    /// The template `name` of the set, counted against the include depth
+    /// until the matching [`Renderer::leave`].
+    fn enter(&mut self, name: &str) -> Result<&'a Template, FillError> {
+        let template = self
+            .templates
+            .and_then(|set| set.get(name))
+            .ok_or_else(|| FillError::UnknownTemplate(name.to_string()))?;
+        self.enter_section(name)?;
+        Ok(template)
+    }
+
+    fn enter_section(&mut self, name: &str) -> Result<(), FillError> {
+        let max = self.options.limits.max_template_depth;
+        if self.includes >= max {
+            return Err(FillError::IncludeTooDeep(name.to_string(), max));
+        }
+        self.includes += 1;
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.includes -= 1;
+    }
+
+    /// Fail once `out` would grow past the output limit.
+    fn check_output(&self, len: usize) -> Result<(), FillError> {
+        let max = self.options.limits.max_output_bytes;
+        if len > max {
+            return Err(FillError::OutputTooLarge(max));
+        }
+        Ok(())
+    }
+
+    fn write_segments(
+        &mut self,
+        source: &'a str,
+        segments: &'a [Segment],
+        out: &mut String,
+    ) -> Result<(), FillError> {
+        for segment in segments {
+            match segment {
+                Segment::Partial(name) => {
+                    let template = self.enter(name)?;
+                    let outer = self.template_name.replace(name);
+                    self.write_segments(&template.source, &template.segments, out)?;
+                    self.template_name = outer;
+                    self.leave();
+                }
+                Segment::Block(name, body) => {
+                    // the outermost override wins
+                    match self.overrides.iter().find(|o| o.block == name) {
+                        Some(o) => {
+                            let (src, body, owner) = (o.source, o.body, o.template_name);
+                            let outer = std::mem::replace(&mut self.template_name, owner);
+                            self.enter_section(name)?;
+                            self.write_segments(src, body, out)?;
+                            self.leave();
+                            self.template_name = outer;
+                        }
+                        None => self.write_segments(source, body, out)?,
+                    }
+                }
+                Segment::Parent(name, blocks) => {
+                    let template = self.enter(name)?;
+                    let outer = self.overrides.len();
+                    for block in blocks {
+                        if let Segment::Block(block_name, body) = block {
+                            self.overrides.push(Override {
+                                block: block_name,
+                                template_name: self.template_name,
+                                source,
+                                body,
+                            });
+                        }
+                    }
+                    let child = self.template_name.replace(name);
+                    self.write_segments(&template.source, &template.segments, out)?;
+                    self.template_name = child;
+                    self.overrides.truncate(outer);
+                    self.leave();
+                }
+                flat => self.write_flat(source, flat, out)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a literal or a placeholder; sections are only found in
+    /// templates, never in expanded values, and are skipped here.
+    fn write_flat(
+        &mut self,
+        source: &str,
+        segment: &Segment,
+        out: &mut String,
+    ) -> Result<(), FillError> {
+        match segment {
+            Segment::Literal(r) => {
+                self.check_output(out.len() + r.len())?;
+                let start = out.len();
+                out.push_str(&source[r.clone()]);
+                if let (Some(mappings), true) = (&mut self.mappings, self.stack.is_empty()) {
+                    mappings.push(Mapping {
+                        output: start..out.len(),
+                        origin: Origin::Literal {
+                            template: self.template_name.map(str::to_string),
+                            span: r.clone(),
+                        },
+                    });
+                }
+            }
+            Segment::Placeholder(p) => self.write_placeholder(p, out)?,
+            Segment::Partial(_) | Segment::Block(..) | Segment::Parent(..) => {}
+        }
+        Ok(())
+    }
+
+    /// Append the value of `p`, decorated unless it is part of an expanded
+    /// value.
+    fn write_placeholder(&mut self, p: &Placeholder, out: &mut String) -> Result<(), FillError> {
+        self.substitutions += 1;
+        let max = self.options.limits.max_substitutions;
+        if self.substitutions > max {
+            return Err(FillError::TooManySubstitutions(max));
+        }
+        let value = match (self.vars.lookup(p.key()), p.default_value()) {
+            (Some(val), _) => {
+                if self.normalize_values {
+                    let val = self.options.normalize.apply(val);
+                    Cow::Owned(self.resolve_value(p.key(), &val)?.into_owned())
+                } else {
+                    self.resolve_value(p.key(), val)?
+                }
+            }
+            (None, Some(default)) => Cow::Borrowed(default),
+            (None, None) => return Err(FillError::MissingVariable(p.key.clone())),
+        };
+        let written = self.write_value(p, &value, out);
+        // copies of a value (normalised, escaped, expanded) may hold secrets
+        if let Cow::Owned(mut value) = value {
+            value.zeroize();
+        }
+        written
+    }
+
+    /// Append `value`, the text resolved for `p`.
+    fn write_value(
+        &mut self,
+        p: &Placeholder,
+        value: &str,
+        out: &mut String,
+    ) -> Result<(), FillError> {
+        // expanded values are escaped and indented once, where they
+        // land in the template
+        self.check_output(out.len() + value.len())?;
+        if !self.stack.is_empty() {
+            out.push_str(value);
+            return Ok(());
+        }
+        let start = out.len();
+        self.write_decorated(p, value, out)?;
+        if let Some(slots) = &mut self.slots {
+            slots.push(Slot {
+                placeholder: p.clone(),
+                range: start..out.len(),
+            });
+        }
+        if let Some(mappings) = &mut self.mappings {
+            mappings.push(Mapping {
+                output: start..out.len(),
+                origin: Origin::Variable {
+                    key: p.key.clone(),
+                    template: self.template_name.map(str::to_string),
+                    span: p.span(),
+                },
+            });
+        }
+        Ok(())
+    }
+
+    /// Escape, fence, tag and indent `value` (in that order) as `p` and the
+    /// options request, then append it to `out`.
+    ///
+    /// Escaping, fences and tags grow the value by a constant factor at most,
+    /// so they are checked against the output limit once written; indentation
+    /// adds the prefix to every line, so its size is checked before writing.
+    fn write_decorated(
+        &self,
+        p: &Placeholder,
+        value: &str,
+        out: &mut String,
+    ) -> Result<(), FillError> {
+        let Decorations {
+            escape,
+            fence,
+            tag,
+            indent,
+        } = p.decorations(self.options);
+        if fence.is_none() && !tag && !(indent && value.contains('\n')) {
+            escape.push(out, value);
+            return self.check_output(out.len());
+        }
+        let mut text = escape.apply(value);
+        if let Some(info) = fence {
+            text = Cow::Owned(fenced(&text, info));
+        }
+        if tag {
+            text = Cow::Owned(tagged(&text, p.key()));
+        }
+        let mut len = out.len() + text.len();
+        if indent && text.contains('\n') {
+            let prefix = continuation_prefix(out);
+            // at most one prefix per line break
+            len += prefix.len() * text.matches('\n').count();
+            self.check_output(len)?;
+            push_indented(out, &text, &prefix);
+        } else {
+            self.check_output(len)?;
+            out.push_str(&text);
+        }
+        Ok(())
+    }
+
+    /// The text to insert for `key`, after the [`ValuePolicy`] and expansion.
+    fn resolve_value<'v>(&mut self, key: &str, value: &'v str) -> Result<Cow<'v, str>, FillError> {
+        if self.options.value_policy == ValuePolicy::Allow && !self.options.expand_values {
+            return Ok(Cow::Borrowed(value));
+        }
+        let has_syntax = value.contains("{{") || value.contains("}}");
+        Ok(match self.options.value_policy {
+            ValuePolicy::Reject if has_syntax => {
+                return Err(FillError::TemplateSyntaxInValue(key.to_string()))
+            }
+            ValuePolicy::Escape if has_syntax => {
+                let mut out = String::with_capacity(value.len() + 4);
+                push_neutralised(&mut out, value);
+                Cow::Owned(out)
+            }
+            _ if self.options.expand_values && value.contains("{{") => {
+                Cow::Owned(self.expand(key, value)?)
+            }
+            _ => Cow::Borrowed(value),
+        })
+    }
+
+    /// Render the value of `key` against the same variables.
+    fn expand(&mut self, key: &str, value: &str) -> Result<String, FillError> {
+        if let Some(done) = self.expanded.get(key) {
+            return Ok(done.clone());
+        }
+        if let Some(pos) = self.stack.iter().position(|k| k == key) {
+            let mut cycle = self.stack[pos..].to_vec();
+            cycle.push(key.to_string());
+            return Err(FillError::ExpansionCycle(cycle));
+        }
+        let max_depth = self.options.limits.max_recursion;
+        if self.stack.len() >= max_depth {
+            return Err(FillError::ExpansionTooDeep(key.to_string(), max_depth));
+        }
+        let segments = parse_segments(value, self.options, false)?;
+        self.stack.push(key.to_string());
+        let mut out = String::with_capacity(value.len());
+        for segment in &segments {
+            self.write_flat(value, segment, &mut out)?;
+        }
+        self.stack.pop();
+        self.expanded.insert(key.to_string(), out.clone());
+        Ok(out)
+    }
+}
+
+/// Prefix for the continuation lines of a value inserted at the end of `out`:
+/// the current line's leading whitespace and `>` quote markers are kept, every
+/// other char (list markers, text) becomes a space so the value stays aligned.
+pub(crate) fn continuation_prefix(out: &str) -> String {
+    let line = &out[out.rfind('\n').map_or(0, |i| i + 1)..];
+    let mut in_markers = true;
+    line.chars()
+        .map(|c| {
+            if c.is_whitespace() || (c == '>' && in_markers) {
+                c
+            } else {
+                in_markers = false;
+                ' '
+            }
+        })
+        .collect()
+}
+
+/// Append `value`, starting every line after the first with `prefix`. Blank
+/// lines get the prefix without trailing whitespace.
+fn push_indented(out: &mut String, value: &str, prefix: &str) {
+    let mut lines = value.split('\n');
+    if let Some(first) = lines.next() {
+        out.push_str(first);
+    }
+    let mut lines = lines.peekable();
+    while let Some(line) = lines.next() {
+        out.push('\n');
+        if line.is_empty() && lines.peek().is_none() {
+            break; // trailing newline
+        }
+        if line.trim().is_empty() {
+            out.push_str(prefix.trim_end());
+        } else {
+            out.push_str(prefix);
+        }
+        out.push_str(line);
+    }
+}
+
+/// Append `value` with a space between consecutive `{` or `}` characters.
+fn push_neutralised(out: &mut String, value: &str) {
+    let mut prev = None;
+    for c in value.chars() {
+        if matches!(c, '{' | '}') && prev == Some(c) {
+            out.push(' ');
+        }
+        out.push(c);
+        prev = Some(c);
+    }
+}
+
+/// Whether `key` follows the strict grammar: a non-empty run of Unicode
+/// alphanumerics, `_`, `-`, `.`, `[` and `]`, plus the other `XID_Continue`
+/// characters (combining marks...) after the first one (e.g. `crate_name`,
+/// `author.email`, `items[0]`, `opt-level`, decomposed `pre\u{301}nom`).
+fn is_valid_key(key: &str) -> bool {
+    if key.is_ascii() {
+        // the same rule on bytes: most keys, and the fast path's only ones
+        return !key.is_empty()
+            && key.bytes().all(|b| {
+                b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'.' | b'[' | b']')
+            });
+    }
+    key.chars().enumerate().all(|(i, c)| {
+        c.is_alphanumeric()
+            || matches!(c, '_' | '-' | '.' | '[' | ']')
+            || (i > 0 && unicode_ident::is_xid_continue(c))
+    })
+}
+
+/// Narrow `range` of `src` to exclude surrounding whitespace.
+fn trim_range(src: &str, range: Range<usize>) -> Range<usize> {
+    let text = &src[range.clone()];
+    let start = range.start + (text.len() - text.trim_start().len());
+    start..start + text.trim().len()
+}
+
+/// Split `range` of `src` on `sep`, ignoring separators inside `"..."` literals.
+fn split_outside_quotes(src: &str, range: Range<usize>, sep: char) -> Vec<Range<usize>> {
+    let mut parts = Vec::new();
+    let mut part_start = range.start;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (idx, c) in src[range.clone()].char_indices() {
+        let idx = range.start + idx;
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            c if c == sep && !in_string => {
+                parts.push(part_start..idx);
+                part_start = idx + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(part_start..range.end);
+    parts
+}
+
+/// Parse a filter argument: a `"quoted"` string (with `\"` and `\\` escapes)
+/// or a bare word.
+fn parse_argument(text: &str) -> Option<String> {
+    if let Some(inner) = text.strip_prefix('"') {
+        let inner = inner.strip_suffix('"')?;
+        let mut out = String::with_capacity(inner.len());
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => out.push(chars.next()?),
+                '"' => return None,
+                c => out.push(c),
+            }
+        }
+        Some(out)
+    } else if !text.is_empty() && !text.contains(|c: char| c.is_whitespace() || c == '"') {
+        Some(text.to_string())
+    } else {
+        None
+    }
+}
+
+/// Parse `name` or `name(arg, ...)` into a [`Filter`].
+fn parse_filter(src: &str, range: Range<usize>) -> Result<Filter, FillError> {
+    let text = &src[range.clone()];
+    let invalid = || FillError::InvalidFilter(text.to_string(), range.clone());
+    let (name, args) = match text.find('(') {
+        Some(open) => {
+            let inner = text[open + 1..].strip_suffix(')').ok_or_else(invalid)?;
+            let inner_start = range.start + open + 1;
+            let args = if inner.trim().is_empty() {
+                Vec::new()
+            } else {
+                split_outside_quotes(src, inner_start..inner_start + inner.len(), ',')
+                    .into_iter()
+                    .map(|r| parse_argument(&src[trim_range(src, r)]))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(invalid)?
+            };
+            (text[..open].trim_end(), args)
+        }
+        None => (text, Vec::new()),
+    };
+    match (name, args.as_slice()) {
+        ("default", [value]) => Ok(Filter::Default(value.clone())),
+        ("fence", []) => Ok(Filter::Fence(String::new())),
+        ("fence", [info]) if !info.contains(['`', '\n']) => Ok(Filter::Fence(info.clone())),
+        ("tagged", []) => Ok(Filter::Tagged(true)),
+        ("untagged", []) => Ok(Filter::Tagged(false)),
+        ("indent", []) => Ok(Filter::Indent(true)),
+        ("noindent", []) => Ok(Filter::Indent(false)),
+        (name, []) => Escape::from_name(name)
+            .map(Filter::Escape)
+            .ok_or_else(invalid),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parse the strict placeholder expression `key ( | filter )*` found in `range`.
+fn parse_expression(src: &str, range: Range<usize>) -> Result<(String, Vec<Filter>), FillError> {
+    if !src[range.clone()].contains('|') {
+        let key_range = trim_range(src, range);
+        let key = &src[key_range.clone()];
+        if !is_valid_key(key) {
+            return Err(FillError::InvalidPlaceholderName(
+                key.to_string(),
+                key_range,
+            ));
+        }
+        return Ok((key.to_string(), Vec::new()));
+    }
+    let mut parts = split_outside_quotes(src, range, '|').into_iter();
+    let key_range = parts.next().map(|r| trim_range(src, r)).unwrap_or_default();
+    let key = &src[key_range.clone()];
+    if !is_valid_key(key) {
+        return Err(FillError::InvalidPlaceholderName(
+            key.to_string(),
+            key_range,
+        ));
+    }
+    let filters = parts
+        .map(|r| parse_filter(src, trim_range(src, r)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((key.to_string(), filters))
+}
+
+/// Byte index of the next occurrence of `pair` (`{{` or `}}`) at or after
+/// `from`, located with a memchr-style byte search for its first byte.
+///
+/// The pair is ASCII, so the index found is always on a char boundary.
+fn find_pair(text: &str, from: usize, pair: &[u8; 2]) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = from;
+    while let Some(offset) = bytes[i..].iter().position(|&b| b == pair[0]) {
+        let at = i + offset;
+        if bytes.get(at + 1) == Some(&pair[1]) {
+            return Some(at);
+        }
+        i = at + 1;
+    }
+    None
+}
+
+/// Largest template, in bytes, whose [`DirectPlan`] is kept between calls.
+const DIRECT_PLAN_MAX_SOURCE: usize = 64 * 1024;
+
+thread_local! {
+    /// The last plan built on this thread: prompts are usually filled from the
+    /// same template again and again. It holds the template text and keys,
+    /// never a value.
+    static LAST_PLAN: RefCell<Option<DirectPlan>> = const { RefCell::new(None) };
+}
+
+/// A template split for [`render_direct`]: literal runs, each followed by a
+/// placeholder, then the tail.
+struct DirectPlan {
+    /// The template the plan was built from, empty when it is not cached.
+    source: String,
+    key_syntax: KeySyntax,
+    /// Each literal (a range of the template) with the index of the key that
+    /// follows it in `keys`.
+    pieces: Vec<(Range<usize>, usize)>,
+    /// Distinct keys, in order of first use, with their number of uses.
+    keys: Vec<(Box<str>, usize)>,
+    /// Start of the literal after the last placeholder.
+    tail: usize,
+    /// Total length of the literals, tail included.
+    literal_len: usize,
+}
+
+impl DirectPlan {
+    /// Split `source`; `None` when the full engine is needed.
+    fn build(source: &str, key_syntax: KeySyntax) -> Option<Self> {
+        let mut pieces = Vec::new();
+        let mut keys: Vec<(Box<str>, usize)> = Vec::new();
+        let mut literal_len = 0;
+        let mut i = 0;
+        while let Some(start) = find_pair(source, i, b"{{") {
+            let key_start = start + 2;
+            let end = find_pair(source, key_start, b"}}")?;
+            // ASCII keys only: Unicode trimming is left to the engine, which
+            // then applies the same grammar (`tests/property.rs` checks both agree)
+            let key = source[key_start..end].trim_ascii();
+            let valid = !key.is_empty()
+                && key.is_ascii()
+                && match key_syntax {
+                    KeySyntax::Strict => is_valid_key(key),
+                    KeySyntax::Permissive => true,
+                };
+            if !valid {
+                return None;
+            }
+            let index = match keys.iter().position(|(k, _)| **k == *key) {
+                Some(index) => index,
+                None => {
+                    keys.push((key.into(), 0));
+                    keys.len() - 1
+                }
+            };
+            keys[index].1 += 1;
+            literal_len += start - i;
+            pieces.push((i..start, index));
+            i = end + 2;
+        }
+        Some(DirectPlan {
+            source: String::new(),
+            key_syntax,
+            pieces,
+            keys,
+            tail: i,
+            literal_len: literal_len + source.len() - i,
+        })
+    }
+
+    /// Fill the plan of `source` from `vars`; `None` when the full engine is
+    /// needed.
+    fn fill<L: Lookup + ?Sized>(
+        &self,
+        source: &str,
+        vars: &L,
+        options: &FillOptions,
+    ) -> Option<String> {
+        let limits = &options.limits;
+        if self.pieces.len() > limits.max_placeholders
+            || self.pieces.len() > limits.max_substitutions
+        {
+            return None;
+        }
+        let guard_values = options.expand_values || options.value_policy != ValuePolicy::Allow;
+        // one lookup per distinct key
+        let mut values: Vec<&str> = Vec::with_capacity(self.keys.len());
+        let mut len = self.literal_len;
+        for (key, uses) in &self.keys {
+            let value = vars.lookup(key)?;
+            if guard_values && value.contains(['{', '}']) {
+                return None;
+            }
+            len = len.checked_add(value.len().checked_mul(*uses)?)?;
+            values.push(value);
+        }
+        if len > limits.max_output_bytes {
+            return None;
+        }
+        // bytes, then one check: cheaper than a char boundary test per slice,
+        // and the pieces cut the template next to ASCII braces
+        let bytes = source.as_bytes();
+        let mut out = Vec::with_capacity(len);
+        for (literal, index) in &self.pieces {
+            out.extend_from_slice(&bytes[literal.clone()]);
+            out.extend_from_slice(values[*index].as_bytes());
+        }
+        out.extend_from_slice(&bytes[self.tail..]);
+        String::from_utf8(out).ok()
+    }
+}
+
+/// Single-pass substitution used by [`fill_template_with`](crate::fill_template_with)
+/// when no filter, decoration, normalisation or expansion is involved: the
+/// template is split once into literal runs (located with [`find_pair`]) and
+/// keys, each distinct key is looked up once, and the output is allocated at
+/// its exact size. The split of the last template of up to
+/// [`DIRECT_PLAN_MAX_SOURCE`] bytes is kept per thread, so filling the same
+/// template again only copies slices.
+///
+/// Returns `None` whenever the full engine is needed, errors included, so the
+/// error reported is always the one [`Template`] gives.
+pub(crate) fn render_direct<L: Lookup + ?Sized>(
+    source: &str,
+    vars: &L,
+    options: &FillOptions,
+) -> Option<String> {
+    let plain = options.escape == Escape::None
+        && !options.indent
+        && !options.tag_all
+        && options.tagged.is_empty()
+        && options.normalize == Normalization::None;
+    if !plain {
+        return None;
+    }
+    // taken out of the cell, not borrowed: a `Lookup` may render in turn
+    let plan = match LAST_PLAN.with(RefCell::take) {
+        Some(plan) if plan.key_syntax == options.key_syntax && plan.source == source => plan,
+        cached => {
+            let Some(mut plan) = DirectPlan::build(source, options.key_syntax) else {
+                // a template for the engine does not evict the cached one
+                LAST_PLAN.with(|last| last.replace(cached));
+                return None;
+            };
+            if source.len() <= DIRECT_PLAN_MAX_SOURCE {
+                plan.source = source.to_owned();
+            }
+            plan
+        }
+    };
+    let out = plan.fill(source, vars, options);
+    if !plan.source.is_empty() {
+        LAST_PLAN.with(|last| last.replace(Some(plan)));
+    }
+    out
+}
+
+/// Whether `name` can name a template of a set: [`is_valid_key`] parts joined
+/// by `/` (e.g. `support/signature`).
+fn is_valid_template_name(name: &str) -> bool {
+    name.split('/').all(is_valid_key)
+}
+
+/// A `{{$block}}` or `{{< parent}}` section waiting for its `{{/name}}`.
+struct OpenSection {
+    parent: bool,
+    name: String,
+    span: Range<usize>,
+    /// Segments of the enclosing level, resumed once the section closes.
+    outer: Vec<Segment>,
+}
+
+/// Split `template` into segments. With `sections` (templates, not values)
+/// and the strict grammar, `{{> name}}`, `{{$name}}`, `{{< name}}` and
+/// `{{/name}}` are section tags rather than placeholders.
+fn parse_segments(
+    template: &str,
+    options: &FillOptions,
+    sections: bool,
+) -> Result<Vec<Segment>, FillError> {
+    let sections = sections && options.key_syntax == KeySyntax::Strict;
+    let mut open: Vec<OpenSection> = Vec::new();
+    let mut segments = Vec::new();
+    let mut placeholders = 0usize;
+    let mut literal_start = 0usize;
+    let mut i = 0usize;
+    while let Some(start) = find_pair(template, i, b"{{") {
+        let key_start = start + 2;
+        let Some(j) = find_pair(template, key_start, b"}}") else {
+            return Err(FillError::UnclosedPlaceholder(start));
+        };
+        let raw = &template[key_start..j];
+        if raw.trim().is_empty() {
+            return Err(FillError::EmptyPlaceholder(key_start));
+        }
+        placeholders += 1;
+        if placeholders > options.limits.max_placeholders {
+            return Err(FillError::TooManyPlaceholders(
+                options.limits.max_placeholders,
+            ));
+        }
+        let sigil = raw
+            .trim_start()
+            .chars()
+            .next()
+            .filter(|c| "><$/".contains(*c));
+        if let Some(sigil) = sigil.filter(|_| sections) {
+            if literal_start < start {
+                segments.push(Segment::Literal(literal_start..start));
+            }
+            let lead = raw.len() - raw.trim_start().len();
+            let name_range = trim_range(template, key_start + lead + 1..j);
+            let name = template[name_range.clone()].to_string();
+            if !is_valid_template_name(&name) || (sigil == '$' && !is_valid_key(&name)) {
+                return Err(FillError::InvalidPlaceholderName(name, name_range));
+            }
+            i = j + 2;
+            literal_start = i;
+            match sigil {
+                '>' => segments.push(Segment::Partial(name)),
+                '/' => {
+                    let section = match open.pop() {
+                        Some(section) if section.name == name => section,
+                        _ => return Err(FillError::UnbalancedSection(name, start..i)),
+                    };
+                    let body = std::mem::replace(&mut segments, section.outer);
+                    segments.push(if section.parent {
+                        let blocks = body
+                            .into_iter()
+                            .filter(|s| matches!(s, Segment::Block(..)))
+                            .collect();
+                        Segment::Parent(name, blocks)
+                    } else {
+                        Segment::Block(name, body)
+                    });
+                }
+                _ => open.push(OpenSection {
+                    parent: sigil == '<',
+                    name,
+                    span: start..i,
+                    outer: std::mem::take(&mut segments),
+                }),
+            }
+            continue;
+        }
+        let (key, filters) = match options.key_syntax {
+            KeySyntax::Strict => parse_expression(template, key_start..j)?,
+            KeySyntax::Permissive => (raw.trim().to_string(), Vec::new()),
+        };
+        if literal_start < start {
+            segments.push(Segment::Literal(literal_start..start));
+        }
+        // move past "}}"
+        i = j + 2;
+        segments.push(Segment::Placeholder(Placeholder {
+            key,
+            span: start..i,
+            filters,
+        }));
+        literal_start = i;
+    }
+    if let Some(section) = open.pop() {
+        return Err(FillError::UnbalancedSection(section.name, section.span));
+    }
+    if literal_start < template.len() {
+        segments.push(Segment::Literal(literal_start..template.len()));
+    }
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RenderLimits;
+
+    fn permissive() -> FillOptions {
+        FillOptions {
+            key_syntax: KeySyntax::Permissive,
+            ..FillOptions::default()
+        }
+    }
+
+    fn expanding() -> FillOptions {
+        FillOptions {
+            expand_values: true,
+            ..FillOptions::default()
+        }
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn accepts_identifier_like_keys() {
+        let tpl = Template::parse(
+            "{{ crate_name }} {{author.email}} {{items[0]}} {{opt-level}} {{público}}",
+        )
+        .unwrap();
+        assert_eq!(
+            tpl.keys().into_iter().collect::<Vec<_>>(),
+            [
+                "author.email",
+                "crate_name",
+                "items[0]",
+                "opt-level",
+                "público"
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_space_inside_key() {
+        let err = Template::parse("Crate: {{ crate name }}").unwrap_err();
+        assert_eq!(
+            err,
+            FillError::InvalidPlaceholderName("crate name".to_string(), 10..20)
+        );
+    }
+
+    #[test]
+    fn rejects_newline_and_brace_inside_key() {
+        assert!(matches!(
+            Template::parse("{{a\nb}}"),
+            Err(FillError::InvalidPlaceholderName(_, _))
+        ));
+        assert!(matches!(
+            Template::parse("{{a}b}}"),
+            Err(FillError::InvalidPlaceholderName(k, _)) if k == "a}b"
+        ));
+    }
+
+    #[test]
+    fn permissive_keeps_old_behaviour() {
+        let tpl = Template::parse_with("{{ crate name }}", &permissive()).unwrap();
+        let mut vars = HashMap::new();
+        vars.insert("crate name".to_string(), "x".to_string());
+        assert_eq!(tpl.render(&vars).unwrap(), "x");
+    }
+
+    #[test]
+    fn placeholder_spans_cover_braces() {
+        let tpl = Template::parse("ab{{ x }}cd").unwrap();
+        let span = tpl.placeholders().next().map(Placeholder::span);
+        assert_eq!(span, Some(2..9));
+    }
+
+    #[test]
+    fn default_filter_fills_missing_variable() {
+        let tpl = Template::parse(r#"{{ lang | default("fr, \"en\"") }}"#).unwrap();
+        let vars = HashMap::new();
+        assert_eq!(tpl.render(&vars).unwrap(), r#"fr, "en""#);
+        assert!(tpl.required_keys().is_empty());
+    }
+
+    #[test]
+    fn report_lists_used_unused_and_defaulted() {
+        let tpl = Template::parse("{{a}} {{a}} {{b | default(x)}} {{c | default(y)}}").unwrap();
+        let mut vars = HashMap::new();
+        vars.insert("a".to_string(), "1".to_string());
+        vars.insert("c".to_string(), "3".to_string());
+        vars.insert("license".to_string(), "MIT".to_string());
+        let rendered = tpl.render_with_report(&vars).unwrap();
+        assert_eq!(rendered.output, "1 1 x 3");
+        let names = |s: &BTreeSet<String>| s.iter().cloned().collect::<Vec<_>>();
+        assert_eq!(names(&rendered.report.used), ["a", "c"]);
+        assert_eq!(names(&rendered.report.unused), ["license"]);
+        assert_eq!(names(&rendered.report.defaulted), ["b"]);
+
+        // keys reached through expanded values count too
+        let tpl = Template::parse_with("{{a}}", &expanding()).unwrap();
+        vars.insert("a".to_string(), "{{c}}{{d | default(z)}}".to_string());
+        let rendered = tpl.render_with_report(&vars).unwrap();
+        assert_eq!(rendered.output, "3z");
+        assert_eq!(names(&rendered.report.used), ["a", "c"]);
+        assert_eq!(names(&rendered.report.defaulted), ["d"]);
+    }
+
+    #[test]
+    fn rejects_unknown_or_malformed_filters() {
+        assert_eq!(
+            Template::parse("{{a | shout}}").unwrap_err(),
+            FillError::InvalidFilter("shout".to_string(), 6..11)
+        );
+        assert!(matches!(
+            Template::parse("{{a | default(\"x)}}"),
+            Err(FillError::InvalidFilter(_, _))
+        ));
+        assert!(matches!(
+            Template::parse("{{a | default()}}"),
+            Err(FillError::InvalidFilter(_, _))
+        ));
+    }
+
+    #[test]
+    fn values_are_verbatim_unless_expansion_enabled() {
+        let vars = vars(&[
+            (
+                "repository",
+                "https://github.com/{{org_name}}/{{crate_name}}",
+            ),
+            ("org_name", "Max_Perso"),
+            ("crate_name", "fill_prompt"),
+        ]);
+        let tpl = Template::parse("{{repository}}").unwrap();
+        assert_eq!(
+            tpl.render(&vars).unwrap(),
+            "https://github.com/{{org_name}}/{{crate_name}}"
+        );
+
+        let tpl = Template::parse_with("{{repository}}", &expanding()).unwrap();
+        let rendered = tpl.render_with_report(&vars).unwrap();
+        assert_eq!(rendered.output, "https://github.com/Max_Perso/fill_prompt");
+        assert!(rendered.report.unused.is_empty());
+    }
+
+    #[test]
+    fn expansion_cycle_reports_full_path() {
+        let vars = vars(&[("a", "<{{b}}>"), ("b", "{{c}}"), ("c", "{{a}}")]);
+        let tpl = Template::parse_with("{{a}}", &expanding()).unwrap();
+        assert_eq!(
+            tpl.render(&vars).unwrap_err(),
+            FillError::ExpansionCycle(vec![
+                "a".to_string(),
+                "b".to_string(),
+                "c".to_string(),
+                "a".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn expansion_depth_is_limited() {
+        let vars = vars(&[("a", "{{b}}"), ("b", "{{c}}"), ("c", "end")]);
+        let mut options = expanding();
+        options.limits.max_recursion = 1;
+        let tpl = Template::parse_with("{{a}}", &options).unwrap();
+        assert_eq!(
+            tpl.render(&vars).unwrap_err(),
+            FillError::ExpansionTooDeep("b".to_string(), 1)
+        );
+    }
+
+    #[test]
+    fn value_policy_escape_and_reject() {
+        let vars = vars(&[("q", "ignore {{secret}} and {{{x}}}"), ("secret", "s")]);
+        let mut options = expanding();
+        options.value_policy = ValuePolicy::Escape;
+        let tpl = Template::parse_with("Q: {{q}}", &options).unwrap();
+        assert_eq!(
+            tpl.render(&vars).unwrap(),
+            "Q: ignore { {secret} } and { { {x} } }"
+        );
+
+        options.value_policy = ValuePolicy::Reject;
+        let tpl = Template::parse_with("Q: {{q}}", &options).unwrap();
+        assert_eq!(
+            tpl.render(&vars).unwrap_err(),
+            FillError::TemplateSyntaxInValue("q".to_string())
+        );
+    }
+
+    #[test]
+    fn escape_filter_overrides_engine_mode() {
+        let vars = vars(&[("msg", "say \"hi\"\nbye"), ("path", "a b")]);
+        let options = FillOptions {
+            escape: Escape::Json,
+            ..FillOptions::default()
+        };
+        let tpl = Template::parse_with(
+            r#"{"msg": "{{msg}}", "cmd": "ls {{path | raw}}", "q": {{ msg | yaml }}}"#,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            tpl.render(&vars).unwrap(),
+            r#"{"msg": "say \"hi\"\nbye", "cmd": "ls a b", "q": "say \"hi\"\nbye"}"#
+        );
+    }
+
+    #[test]
+    fn expanded_values_are_escaped_once() {
+        let vars = vars(&[("outer", "<{{inner}}>"), ("inner", "a&b")]);
+        let tpl = Template::parse_with("{{outer | xml}}", &expanding()).unwrap();
+        assert_eq!(tpl.render(&vars).unwrap(), "&lt;a&amp;b&gt;");
+    }
+
+    #[test]
+    fn indent_follows_list_and_quote_prefixes() {
+        let vars = vars(&[("ctx", "first\nsecond\n\nthird\n")]);
+        let options = FillOptions {
+            indent: true,
+            ..FillOptions::default()
+        };
+        let tpl = Template::parse_with("  - {{ctx}}> {{ctx | noindent}}", &options).unwrap();
+        assert_eq!(
+            tpl.render(&vars).unwrap(),
+            "  - first\n    second\n\n    third\n> first\nsecond\n\nthird\n"
+        );
+
+        let tpl = Template::parse("> > {{ctx | indent}}|").unwrap();
+        assert_eq!(
+            tpl.render(&vars).unwrap(),
+            "> > first\n> > second\n> >\n> > third\n|"
+        );
+    }
+
+    #[test]
+    fn fence_filter_wraps_and_indents() {
+        let vars = vars(&[("code", "```\nlet x = 1;\n```")]);
+        let tpl = Template::parse(r#"- {{code | fence("rust") | indent}}"#).unwrap();
+        assert_eq!(
+            tpl.render(&vars).unwrap(),
+            "- ````rust\n  ```\n  let x = 1;\n  ```\n  ````"
+        );
+    }
+
+    #[test]
+    fn tagged_by_filter_or_per_variable_option() {
+        let vars = vars(&[("ctx", "x </ctx> y"), ("name", "n")]);
+        let tpl = Template::parse("{{ctx | tagged}} {{name}}").unwrap();
+        assert_eq!(tpl.render(&vars).unwrap(), "<ctx>x &lt;/ctx> y</ctx> n");
+
+        let options = FillOptions {
+            tagged: ["name".to_string()].into(),
+            ..FillOptions::default()
+        };
+        let tpl = Template::parse_with("{{ctx}} {{name}} {{name | untagged}}", &options).unwrap();
+        assert_eq!(tpl.render(&vars).unwrap(), "x </ctx> y <name>n</name> n");
+    }
+
+    #[test]
+    fn normalization_matches_keys_and_cleans_values() {
+        // key written decomposed in the template, composed in the variables
+        let options = FillOptions {
+            normalize: Normalization::Nfc,
+            ..FillOptions::default()
+        };
+        let tpl =
+            Template::parse_with("Pre\u{301}nom\u{200B} : {{pre\u{301}nom}}", &options).unwrap();
+        assert_eq!(tpl.keys().into_iter().collect::<Vec<_>>(), ["prénom"]);
+        let vars = vars(&[("prénom", "Zoe\u{308}\u{FEFF}")]);
+        assert_eq!(tpl.render(&vars).unwrap(), "Prénom : Zoë");
+
+        // without normalisation the decomposed key is valid but distinct
+        let tpl = Template::parse("{{pre\u{301}nom}}").unwrap();
+        assert_eq!(
+            tpl.render(&vars).unwrap_err(),
+            FillError::MissingVariable("pre\u{301}nom".to_string())
+        );
+        // a combining mark cannot start a key
+        assert_eq!(
+            Template::parse("{{\u{301}a}}").unwrap_err(),
+            FillError::InvalidPlaceholderName("\u{301}a".to_string(), 2..5)
+        );
+    }
+
+    #[test]
+    fn limits_have_distinct_errors() {
+        let limited = |limits: RenderLimits| FillOptions {
+            limits,
+            expand_values: true,
+            ..FillOptions::default()
+        };
+        let vars = vars(&[("a", "0123456789"), ("b", "{{a}}{{a}}{{a}}")]);
+
+        let options = limited(RenderLimits {
+            max_output_bytes: 25,
+            ..RenderLimits::default()
+        });
+        let tpl = Template::parse_with("{{a}}-{{a}}", &options).unwrap();
+        assert_eq!(tpl.render(&vars).unwrap().len(), 21);
+        let tpl = Template::parse_with("{{b}}", &options).unwrap();
+        assert_eq!(
+            tpl.render(&vars).unwrap_err(),
+            FillError::OutputTooLarge(25)
+        );
+        // indentation repeats the prefix on every line of the value
+        let indented = FillOptions {
+            indent: true,
+            ..options.clone()
+        };
+        let tpl = Template::parse_with("          {{v}}", &indented).unwrap();
+        assert!(tpl.render(&self::vars(&[("v", "a b c d")])).is_ok());
+        assert_eq!(
+            tpl.render(&self::vars(&[("v", "a\nb\nc\nd")])).unwrap_err(),
+            FillError::OutputTooLarge(25)
+        );
+
+        let options = limited(RenderLimits {
+            max_placeholders: 2,
+            ..RenderLimits::default()
+        });
+        assert_eq!(
+            Template::parse_with("{{a}}{{a}}{{a}}", &options).unwrap_err(),
+            FillError::TooManyPlaceholders(2)
+        );
+        // the limit also applies to each expanded value
+        let tpl = Template::parse_with("{{b}}", &options).unwrap();
+        assert_eq!(
+            tpl.render(&vars).unwrap_err(),
+            FillError::TooManyPlaceholders(2)
+        );
+
+        let options = limited(RenderLimits {
+            max_substitutions: 3,
+            ..RenderLimits::default()
+        });
+        let tpl = Template::parse_with("{{b}}", &options).unwrap();
+        assert_eq!(
+            tpl.render(&vars).unwrap_err(),
+            FillError::TooManySubstitutions(3)
+        );
+    }
+
+    #[test]
+    fn untrusted_limits_stop_exponential_expansion() {
+        // each level doubles the output: 2^40 bytes without a limit
+        let mut pairs = vec![("v0".to_string(), "xx".to_string())];
+        for i in 1..40 {
+            pairs.push((
+                format!("v{i}"),
+                format!("{{{{v{}}}}}{{{{v{}}}}}", i - 1, i - 1),
+            ));
+        }
+        let vars: HashMap<String, String> = pairs.into_iter().collect();
+        let options = FillOptions {
+            expand_values: true,
+            limits: RenderLimits {
+                max_recursion: 64,
+                ..RenderLimits::untrusted()
+            },
+            ..FillOptions::default()
+        };
+        let tpl = Template::parse_with("{{v39}}", &options).unwrap();
+        assert_eq!(
+            tpl.render(&vars).unwrap_err(),
+            FillError::OutputTooLarge(1 << 20)
+        );
+    }
+
+    #[test]
+    fn render_cow_borrows_when_there_is_nothing_to_fill() {
+        let tpl = Template::parse("Aucune variable ici.").unwrap();
+        assert!(matches!(tpl.render_cow(&vars(&[])), Ok(Cow::Borrowed(_))));
+        let tpl = Template::parse("Bonjour {{who}}").unwrap();
+        assert!(matches!(
+            tpl.render_cow(&vars(&[("who", "Max")])).as_deref(),
+            Ok("Bonjour Max")
+        ));
+    }
+
+    #[test]
+    fn direct_path_matches_the_engine() {
+        let map = vars(&[("a", "1"), ("b", "{{a}}"), ("name", "Max")]);
+        let cases = [
+            "plain text",
+            "{{a}}-{{ a }}-{{name}}{{a}}",
+            "é {{name}} ü {{ name}}",
+            "{{b}}",
+            "{{missing}}",
+            "{{a",
+            "{{}}",
+            "{{ bad key }}",
+            "{{{a}}}",
+            "{{name | fence}}",
+            "{{missing | default: \"x\"}}",
+        ];
+        for options in [FillOptions::default(), permissive(), expanding()] {
+            for source in cases {
+                let engine = Template::parse_with(source, &options).and_then(|t| t.render(&map));
+                if let Some(out) = render_direct(source, &map, &options) {
+                    assert_eq!(Ok(out), engine, "{source:?}");
+                }
+                assert_eq!(
+                    crate::fill_template_with(source, &map, &options),
+                    engine,
+                    "{source:?}"
+                );
+            }
+        }
+        // anything the direct path cannot do exactly is left to the engine
+        assert_eq!(render_direct("{{b}}", &map, &expanding()), None);
+        assert_eq!(
+            render_direct("{{name | fence}}", &map, &FillOptions::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn direct_plan_is_rebuilt_for_another_template() {
+        let map = vars(&[("a", "1"), ("my key", "2")]);
+        let options = FillOptions::default();
+        for _ in 0..2 {
+            assert_eq!(
+                render_direct("<{{a}}>", &map, &options).as_deref(),
+                Some("<1>")
+            );
+            assert_eq!(
+                render_direct("[{{a}}]", &map, &options).as_deref(),
+                Some("[1]")
+            );
+        }
+        // the grammar is part of the plan
+        assert_eq!(render_direct("{{my key}}", &map, &options), None);
+        assert_eq!(
+            render_direct("{{my key}}", &map, &permissive()).as_deref(),
+            Some("2")
+        );
+        assert_eq!(render_direct("{{my key}}", &map, &options), None);
+
+        // a lookup may fill another template while the plan is in use
+        struct Nested;
+        impl Lookup for Nested {
+            fn lookup(&self, key: &str) -> Option<&str> {
+                let inner = vars(&[("x", "y")]);
+                assert_eq!(crate::render_with("({{x}})", &inner).unwrap(), "(y)");
+                Some(if key == "a" { "A" } else { "B" })
+            }
+        }
+        assert_eq!(
+            render_direct("{{a}}{{b}}{{a}}", &Nested, &options).as_deref(),
+            Some("ABA")
+        );
+    }
+
+    #[test]
+    fn render_lookup_matches_render() {
+        let map = vars(&[
+            ("a", "1"),
+            ("b", "{{a}}+{{a}}"),
+            ("mot", "de\u{301}ja\u{300}"),
+        ]);
+        let pairs: Vec<(&str, &str)> = map.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        let nfc = FillOptions {
+            normalize: Normalization::Nfc,
+            ..FillOptions::default()
+        };
+        for options in [FillOptions::default(), expanding(), nfc] {
+            for source in [
+                "{{a}} {{b}}",
+                "{{mot | fence}} {{x | default(\"?\")}}",
+                "{{x}}",
+            ] {
+                let tpl = Template::parse_with(source, &options).unwrap();
+                assert_eq!(
+                    tpl.render_lookup(&pairs[..]),
+                    tpl.render(&map),
+                    "{source:?}"
+                );
+                assert_eq!(
+                    crate::render_with_options(source, &pairs[..], &options),
+                    tpl.render(&map),
+                    "{source:?}"
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn render_batch_keeps_row_order_and_errors() {
+        use rayon::prelude::*;
+
+        let tpl = Template::parse("{{id}}:{{label}}").unwrap();
+        let rows: Vec<HashMap<String, String>> = (0..1000)
+            .map(|i| {
+                let mut row = vars(&[("label", "x")]);
+                if i % 7 != 0 {
+                    row.insert("id".to_string(), i.to_string());
+                }
+                row
+            })
+            .collect();
+        let results = tpl.render_batch(rows.par_iter());
+        assert_eq!(results.len(), rows.len());
+        for (i, result) in results.iter().enumerate() {
+            if i % 7 == 0 {
+                assert_eq!(result, &Err(FillError::MissingVariable("id".to_string())));
+            } else {
+                assert_eq!(result.as_deref(), Ok(format!("{i}:x").as_str()));
+            }
+        }
+    }
+
+    #[test]
+    fn sections_must_balance_and_stay_out_of_values() {
+        let tpl = Template::parse("{{$intro}}Hi {{who}}{{/intro}} {{> footer}}").unwrap();
+        assert_eq!(tpl.keys(), BTreeSet::from(["who"]));
+        assert_eq!(tpl.includes(), BTreeSet::from(["footer"]));
+        assert_eq!(
+            Template::parse("{{$a}}x{{/b}}").unwrap_err(),
+            FillError::UnbalancedSection("b".to_string(), 7..13)
+        );
+        assert_eq!(
+            Template::parse("x {{< base}}").unwrap_err(),
+            FillError::UnbalancedSection("base".to_string(), 2..12)
+        );
+        assert_eq!(
+            Template::parse("{{> /x}}").unwrap_err(),
+            FillError::InvalidPlaceholderName("/x".to_string(), 4..6)
+        );
+        // the permissive grammar keeps treating them as keys
+        let tpl = Template::parse_with("{{> footer}}", &permissive()).unwrap();
+        assert_eq!(tpl.render(&vars(&[("> footer", "v")])).unwrap(), "v");
+        // a value cannot pull in another template
+        let tpl = Template::parse_with("{{a}}", &expanding()).unwrap();
+        assert_eq!(
+            tpl.render(&vars(&[("a", "{{> secret}}")])).unwrap_err(),
+            FillError::InvalidPlaceholderName("> secret".to_string(), 2..10)
+        );
+    }
+}
@@ -1,43 +1,763 @@
-//! Helpers pour valider les descriptions (nombre de mots).
+//! Helpers pour valider les valeurs des variables.
+//!
+//! Une [`Rule`](crate::validate::Rule) vérifie une valeur ; les règles
+//! fournies couvrent le nombre de mots et de caractères, un motif (feature
+//! `regex`), une liste de valeurs, un intervalle d’entiers, une version
+//! semver, un identifiant de licence SPDX et les valeurs vides. Un
+//! [`Schema`](crate::validate::Schema) associe des règles aux noms de
+//! variables ; il se construit à la main, depuis un front matter
+//! ([`Schema::from_front_matter`](crate::validate::Schema::from_front_matter))
+//! ou depuis un fichier TOML / YAML de même forme
+//! ([`Schema::load`](crate::validate::Schema::load), feature `serde`).
+//! [`Schema::validate`](crate::validate::Schema::validate) s’arrête au
+//! premier échec ; [`Schema::report`](crate::validate::Schema::report) les
+//! liste tous, en texte ou en JSON.
 //!
 //! # Examples
 //!
 //! ```
-//! use fill_prompt::validate::{validate_short, ValidationError};
+//! use std::collections::HashMap;
+//! use fill_prompt::validate::{validate_short, MaxWords, NonEmpty, Schema, SemVer, ValidationError};
 //! let s = "Phrase courte exemple";
 //! assert!(validate_short(s).is_ok());
+//!
+//! let mut schema = Schema::new();
+//! schema.add("title", NonEmpty).add("title", MaxWords(3)).add("version", SemVer);
+//! let vars = HashMap::from([
+//!     ("title".to_string(), "Un titre".to_string()),
+//!     ("version".to_string(), "1.2".to_string()),
+//! ]);
+//! assert_eq!(
+//!     schema.validate(&vars),
+//!     Err(("version".to_string(), ValidationError::InvalidSemver))
+//! );
 //! ```
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
 use thiserror::Error;
 
-/// Erreurs de validation des descriptions.
-#[derive(Debug, Error, PartialEq, Eq)]
+use crate::escape::Escape;
+use crate::front_matter::{self, Format, FrontMatter};
+
+mod spdx_ids;
+
+use spdx_ids::{EXCEPTIONS as SPDX_EXCEPTIONS, LICENSES as SPDX_LICENSES};
+
+/// Version de la liste de licences SPDX connue de la règle [`Spdx`].
+pub const SPDX_LIST_VERSION: &str = spdx_ids::VERSION;
+
+/// Erreurs de validation des valeurs.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
 pub enum ValidationError {
     #[error("trop long: {0} mots (max {1})")]
     TooManyWords(usize, usize),
+    #[error("trop court: {0} mots (min {1})")]
+    TooFewWords(usize, usize),
+    #[error("trop long: {0} caractères (max {1})")]
+    TooManyChars(usize, usize),
+    #[error("trop court: {0} caractères (min {1})")]
+    TooFewChars(usize, usize),
+    /// Valeur hors de la liste autorisée (la valeur n’est pas répétée : elle peut être secrète).
+    #[error("valeur non autorisée (attendu: {})", .0.join(", "))]
+    NotOneOf(Vec<String>),
+    #[error("type attendu: {0}")]
+    WrongType(String),
+    /// Aucune correspondance du motif (donné en paramètre).
+    #[error("ne correspond pas au motif {0}")]
+    PatternMismatch(String),
+    #[error("trop petit (min {0})")]
+    TooSmall(i64),
+    #[error("trop grand (max {0})")]
+    TooLarge(i64),
+    #[error("version semver invalide (attendu: MAJEUR.MINEUR.CORRECTIF)")]
+    InvalidSemver,
+    #[error("expression de licence SPDX invalide ou inconnue")]
+    UnknownLicense,
+    #[error("valeur vide")]
+    Empty,
+    /// Variable requise non fournie (règle `required` d’un rapport).
+    #[error("variable requise manquante")]
+    Missing,
+    /// Variable fournie mais absente du template (règle `unused`).
+    #[error("variable inutilisée par le template")]
+    Unused,
+    /// Motif d’injection de prompt trouvé dans la valeur (règle `safety`),
+    /// avec le genre de trouvaille mais sans l’extrait.
+    #[error("valeur suspecte: {0}")]
+    Suspicious(String),
+}
+
+impl ValidationError {
+    /// La mesure de la valeur (mots, caractères), quand la règle en fait une.
+    /// Jamais la valeur elle-même : un entier hors intervalle n’est pas
+    /// repris, il peut être secret.
+    pub fn actual(&self) -> Option<usize> {
+        match self {
+            ValidationError::TooManyWords(n, _)
+            | ValidationError::TooFewWords(n, _)
+            | ValidationError::TooManyChars(n, _)
+            | ValidationError::TooFewChars(n, _) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// La borne ou l’attente non respectée, quand la règle en a une.
+    pub fn limit(&self) -> Option<Limit> {
+        match self {
+            ValidationError::TooManyWords(_, l)
+            | ValidationError::TooFewWords(_, l)
+            | ValidationError::TooManyChars(_, l)
+            | ValidationError::TooFewChars(_, l) => Some(Limit::Count(*l)),
+            ValidationError::TooSmall(l) | ValidationError::TooLarge(l) => Some(Limit::Integer(*l)),
+            ValidationError::NotOneOf(values) => Some(Limit::OneOf(values.clone())),
+            ValidationError::WrongType(t) | ValidationError::PatternMismatch(t) => {
+                Some(Limit::Text(t.clone()))
+            }
+            ValidationError::InvalidSemver
+            | ValidationError::UnknownLicense
+            | ValidationError::Empty
+            | ValidationError::Missing
+            | ValidationError::Unused
+            | ValidationError::Suspicious(_) => None,
+        }
+    }
+}
+
+/// Borne d’une règle, pour les rapports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Limit {
+    /// Nombre de mots ou de caractères.
+    Count(usize),
+    /// Borne d’un intervalle d’entiers.
+    Integer(i64),
+    /// Type ou motif attendu.
+    Text(String),
+    /// Valeurs autorisées.
+    OneOf(Vec<String>),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Count(n) => write!(f, "{}", n),
+            Limit::Integer(n) => write!(f, "{}", n),
+            Limit::Text(t) => f.write_str(t),
+            Limit::OneOf(values) => f.write_str(&values.join(", ")),
+        }
+    }
 }
 
 /// Compte les mots (séparateur whitespace) de façon simple.
-fn word_count(s: &str) -> usize {
+pub(crate) fn word_count(s: &str) -> usize {
     s.split_whitespace().filter(|w| !w.is_empty()).count()
 }
 
 /// Valide la phrase courte (<= 30 mots).
 pub fn validate_short(s: &str) -> Result<(), ValidationError> {
-    let c = word_count(s);
-    if c <= 30 {
-        Ok(())
-    } else {
-        Err(ValidationError::TooManyWords(c, 30))
-    }
+    MaxWords(30).check(s)
 }
 
 /// Valide le paragraphe de contexte (<= 40 mots).
 pub fn validate_context(s: &str) -> Result<(), ValidationError> {
-    let c = word_count(s);
-    if c <= 40 {
+    MaxWords(40).check(s)
+}
+
+/// Une contrainte sur la valeur d’une variable.
+pub trait Rule: fmt::Debug + Send + Sync {
+    /// Identifiant stable de la règle (`max_words`, `semver`…), le nom du
+    /// champ de front matter correspondant.
+    fn id(&self) -> &'static str;
+
+    /// Vérifie `value` ; l’erreur ne reprend jamais la valeur.
+    fn check(&self, value: &str) -> Result<(), ValidationError>;
+}
+
+/// Au moins N mots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MinWords(pub usize);
+
+impl Rule for MinWords {
+    fn id(&self) -> &'static str {
+        "min_words"
+    }
+
+    fn check(&self, value: &str) -> Result<(), ValidationError> {
+        let c = word_count(value);
+        if c < self.0 {
+            Err(ValidationError::TooFewWords(c, self.0))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Au plus N mots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxWords(pub usize);
+
+impl Rule for MaxWords {
+    fn id(&self) -> &'static str {
+        "max_words"
+    }
+
+    fn check(&self, value: &str) -> Result<(), ValidationError> {
+        let c = word_count(value);
+        if c > self.0 {
+            Err(ValidationError::TooManyWords(c, self.0))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Au moins N caractères.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MinChars(pub usize);
+
+impl Rule for MinChars {
+    fn id(&self) -> &'static str {
+        "min_chars"
+    }
+
+    fn check(&self, value: &str) -> Result<(), ValidationError> {
+        let c = value.chars().count();
+        if c < self.0 {
+            Err(ValidationError::TooFewChars(c, self.0))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Au plus N caractères.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxChars(pub usize);
+
+impl Rule for MaxChars {
+    fn id(&self) -> &'static str {
+        "max_chars"
+    }
+
+    fn check(&self, value: &str) -> Result<(), ValidationError> {
+        let c = value.chars().count();
+        if c > self.0 {
+            Err(ValidationError::TooManyChars(c, self.0))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// La valeur contient une correspondance de l’expression régulière ;
+/// l’ancrer (`^…$`) pour contraindre toute la valeur.
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone)]
+pub struct Pattern(regex::Regex);
+
+#[cfg(feature = "regex")]
+impl Pattern {
+    /// Compile `pattern` (syntaxe du crate `regex`).
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        regex::Regex::new(pattern).map(Pattern)
+    }
+
+    /// Le motif source.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+#[cfg(feature = "regex")]
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+#[cfg(feature = "regex")]
+impl Eq for Pattern {}
+
+#[cfg(feature = "regex")]
+impl Rule for Pattern {
+    fn id(&self) -> &'static str {
+        "pattern"
+    }
+
+    fn check(&self, value: &str) -> Result<(), ValidationError> {
+        if self.0.is_match(value) {
+            Ok(())
+        } else {
+            Err(ValidationError::PatternMismatch(self.as_str().to_string()))
+        }
+    }
+}
+
+/// Une des valeurs de la liste (comparaison exacte).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OneOf(pub Vec<String>);
+
+impl Rule for OneOf {
+    fn id(&self) -> &'static str {
+        "one_of"
+    }
+
+    fn check(&self, value: &str) -> Result<(), ValidationError> {
+        if self.0.iter().any(|v| v == value) {
+            Ok(())
+        } else {
+            Err(ValidationError::NotOneOf(self.0.clone()))
+        }
+    }
+}
+
+/// Un entier (`i64`) entre `min` et `max`, bornes incluses ; sans bornes,
+/// seulement un entier.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IntRange {
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+}
+
+impl Rule for IntRange {
+    fn id(&self) -> &'static str {
+        "range"
+    }
+
+    fn check(&self, value: &str) -> Result<(), ValidationError> {
+        let n: i64 = value
+            .parse()
+            .map_err(|_| ValidationError::WrongType("integer".to_string()))?;
+        if let Some(min) = self.min.filter(|min| n < *min) {
+            return Err(ValidationError::TooSmall(min));
+        }
+        if let Some(max) = self.max.filter(|max| n > *max) {
+            return Err(ValidationError::TooLarge(max));
+        }
+        Ok(())
+    }
+}
+
+/// Une version [semver 2.0](https://semver.org) : `1.2.3`,
+/// `1.0.0-rc.1+build.5`… (sans préfixe `v`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemVer;
+
+impl Rule for SemVer {
+    fn id(&self) -> &'static str {
+        "semver"
+    }
+
+    fn check(&self, value: &str) -> Result<(), ValidationError> {
+        if is_semver(value) {
+            Ok(())
+        } else {
+            Err(ValidationError::InvalidSemver)
+        }
+    }
+}
+
+fn is_semver(s: &str) -> bool {
+    let numeric = |p: &str| {
+        !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()) && (p == "0" || !p.starts_with('0'))
+    };
+    let ident =
+        |p: &str| !p.is_empty() && p.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-');
+    let (rest, build) = match s.split_once('+') {
+        Some((rest, build)) => (rest, Some(build)),
+        None => (s, None),
+    };
+    // le cœur ne contient pas de `-` : le premier ouvre la pré-version
+    let (core, pre) = match rest.split_once('-') {
+        Some((core, pre)) => (core, Some(pre)),
+        None => (rest, None),
+    };
+    let parts: Vec<&str> = core.split('.').collect();
+    parts.len() == 3
+        && parts.iter().all(|p| numeric(p))
+        && pre.is_none_or(|pre| {
+            pre.split('.')
+                .all(|id| ident(id) && (!id.bytes().all(|b| b.is_ascii_digit()) || numeric(id)))
+        })
+        && build.is_none_or(|build| build.split('.').all(ident))
+}
+
+/// Une expression de licence [SPDX](https://spdx.org/licenses/) : un
+/// identifiant de la liste SPDX [`SPDX_LIST_VERSION`] (`MIT`, `Apache-2.0`,
+/// identifiants dépréciés compris, éventuellement suivi de `+`), un
+/// `LicenseRef-…`, ou leur combinaison avec `AND`, `OR`, `WITH` (exception de
+/// la même liste) et des parenthèses. Les identifiants ne tiennent pas compte
+/// de la casse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spdx;
+
+impl Rule for Spdx {
+    fn id(&self) -> &'static str {
+        "spdx"
+    }
+
+    fn check(&self, value: &str) -> Result<(), ValidationError> {
+        let spaced = value.replace('(', " ( ").replace(')', " ) ");
+        let tokens: Vec<&str> = spaced.split_whitespace().collect();
+        let mut at = 0;
+        if spdx_expression(&tokens, &mut at) && at == tokens.len() {
+            Ok(())
+        } else {
+            Err(ValidationError::UnknownLicense)
+        }
+    }
+}
+
+/// `expression := terme ((AND | OR) terme)*` ; avance `at`.
+fn spdx_expression(tokens: &[&str], at: &mut usize) -> bool {
+    if !spdx_term(tokens, at) {
+        return false;
+    }
+    while let Some(&op) = tokens.get(*at) {
+        if !matches!(op, "AND" | "OR" | "and" | "or") {
+            break;
+        }
+        *at += 1;
+        if !spdx_term(tokens, at) {
+            return false;
+        }
+    }
+    true
+}
+
+/// `terme := ( expression ) | licence [WITH exception]`.
+fn spdx_term(tokens: &[&str], at: &mut usize) -> bool {
+    let Some(&token) = tokens.get(*at) else {
+        return false;
+    };
+    *at += 1;
+    if token == "(" {
+        return spdx_expression(tokens, at) && tokens.get(*at) == Some(&")") && {
+            *at += 1;
+            true
+        };
+    }
+    if !spdx_license(token) {
+        return false;
+    }
+    if matches!(tokens.get(*at), Some(&("WITH" | "with"))) {
+        let known = tokens
+            .get(*at + 1)
+            .is_some_and(|e| SPDX_EXCEPTIONS.iter().any(|x| x.eq_ignore_ascii_case(e)));
+        *at += 2;
+        return known;
+    }
+    true
+}
+
+fn spdx_license(token: &str) -> bool {
+    let custom = |id: &str| {
+        id.strip_prefix("LicenseRef-").is_some_and(|rest| {
+            !rest.is_empty()
+                && rest
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.')
+        })
+    };
+    // `DocumentRef-doc:LicenseRef-x` : licence d’un autre document SPDX
+    if let Some((doc, id)) = token.split_once(':') {
+        return doc.starts_with("DocumentRef-") && custom(id);
+    }
+    let id = token.strip_suffix('+').unwrap_or(token);
+    custom(id) || SPDX_LICENSES.iter().any(|l| l.eq_ignore_ascii_case(id))
+}
+
+/// Ni vide ni faite seulement d’espaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonEmpty;
+
+impl Rule for NonEmpty {
+    fn id(&self) -> &'static str {
+        "non_empty"
+    }
+
+    fn check(&self, value: &str) -> Result<(), ValidationError> {
+        if value.trim().is_empty() {
+            Err(ValidationError::Empty)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Des règles par nom de variable, dans l’ordre où les variables ont été
+/// ajoutées : c’est celui des vérifications et des rapports.
+#[derive(Debug, Default)]
+pub struct Schema {
+    rules: Vec<(String, Vec<Box<dyn Rule>>)>,
+}
+
+impl Schema {
+    /// Un schéma sans règle.
+    pub fn new() -> Self {
+        Schema::default()
+    }
+
+    /// Les limites historiques du CLI : `short_description` (<= 30 mots) et
+    /// `context_paragraph` (<= 40 mots).
+    pub fn builtin() -> Self {
+        let mut schema = Schema::new();
+        schema
+            .add("short_description", MaxWords(30))
+            .add("context_paragraph", MaxWords(40));
+        schema
+    }
+
+    /// Les règles déclarées par `front` (type compris, voir
+    /// [`VarSpec::rules`](crate::front_matter::VarSpec::rules)), par ordre
+    /// de nom ; les variables sans règle n’y figurent pas.
+    pub fn from_front_matter(front: &FrontMatter) -> Self {
+        let mut schema = Schema::new();
+        for (name, spec) in &front.variables {
+            let rules = spec.rules();
+            if !rules.is_empty() {
+                schema.rules.push((name.clone(), rules));
+            }
+        }
+        schema
+    }
+
+    /// Lit un schéma écrit comme le contenu d’un front matter (table
+    /// `variables`, sans les délimiteurs) ; seules les règles sont gardées.
+    ///
+    /// # Errors
+    ///
+    /// Celles de [`front_matter::parse`].
+    pub fn parse(format: Format, text: &str) -> anyhow::Result<Self> {
+        Ok(Schema::from_front_matter(&front_matter::parse_block(
+            format, text,
+        )?))
+    }
+
+    /// Lit le fichier de schéma `path` : TOML (`.toml`) ou YAML (`.yaml`,
+    /// `.yml`), voir [`Schema::parse`].
+    ///
+    /// # Errors
+    ///
+    /// Fichier illisible, extension inconnue ou schéma invalide.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let format = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Format::Toml,
+            Some("yaml" | "yml") => Format::Yaml,
+            _ => {
+                return Err(anyhow!(
+                    "unknown schema format for '{}' (expected .toml, .yaml or .yml)",
+                    path.display()
+                ))
+            }
+        };
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read schema '{}'", path.display()))?;
+        Schema::parse(format, &text).with_context(|| format!("invalid schema '{}'", path.display()))
+    }
+
+    /// Ajoute `rule` aux règles de `var`.
+    pub fn add(&mut self, var: impl Into<String>, rule: impl Rule + 'static) -> &mut Self {
+        let var = var.into();
+        match self.rules.iter_mut().find(|(name, _)| *name == var) {
+            Some((_, rules)) => rules.push(Box::new(rule)),
+            None => self.rules.push((var, vec![Box::new(rule)])),
+        }
+        self
+    }
+
+    /// Les règles de `var`, dans l’ordre d’ajout.
+    pub fn rules(&self, var: &str) -> &[Box<dyn Rule>] {
+        self.rules
+            .iter()
+            .find(|(name, _)| name == var)
+            .map_or(&[], |(_, rules)| rules.as_slice())
+    }
+
+    /// Les variables ayant des règles, dans l’ordre d’ajout.
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        self.rules.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Reprend les règles de `other` : pour une variable présente des deux
+    /// côtés, celles de `other` remplacent les siennes, à la même place ;
+    /// les autres s’ajoutent à la fin.
+    pub fn merge(&mut self, other: Schema) {
+        for (var, rules) in other.rules {
+            match self.rules.iter_mut().find(|(name, _)| *name == var) {
+                Some((_, mine)) => *mine = rules,
+                None => self.rules.push((var, rules)),
+            }
+        }
+    }
+
+    /// Vérifie `value` avec les règles de `var` ; s’arrête à la première en
+    /// échec.
+    pub fn check(&self, var: &str, value: &str) -> Result<(), ValidationError> {
+        self.rules(var)
+            .iter()
+            .try_for_each(|rule| rule.check(value))
+    }
+
+    /// Valide les variables de `vars` ayant des règles ; s’arrête au premier
+    /// échec, dans l’ordre des variables du schéma. Les variables absentes
+    /// ne sont pas vérifiées.
+    ///
+    /// # Errors
+    ///
+    /// Le nom de la variable avec son [`ValidationError`].
+    pub fn validate(
+        &self,
+        vars: &HashMap<String, String>,
+    ) -> Result<(), (String, ValidationError)> {
+        for (name, rules) in &self.rules {
+            if let Some(value) = vars.get(name) {
+                rules
+                    .iter()
+                    .try_for_each(|rule| rule.check(value))
+                    .map_err(|e| (name.clone(), e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Vérifie toutes les règles des variables de `vars`, dans l’ordre des
+    /// variables du schéma puis des règles, et rapporte chaque échec. Une
+    /// erreur de type arrête les vérifications de sa variable : les suivantes
+    /// n’auraient pas de sens. Les variables absentes ne sont pas vérifiées.
+    pub fn report(&self, vars: &HashMap<String, String>) -> Report {
+        let mut violations = Vec::new();
+        for (name, rules) in &self.rules {
+            let Some(value) = vars.get(name) else {
+                continue;
+            };
+            for rule in rules {
+                if let Err(error) = rule.check(value) {
+                    let wrong_type = matches!(error, ValidationError::WrongType(_));
+                    violations.push(Violation {
+                        variable: name.clone(),
+                        rule: rule.id(),
+                        error,
+                    });
+                    if wrong_type {
+                        break;
+                    }
+                }
+            }
+        }
+        Report { violations }
+    }
+}
+
+/// Une règle non respectée par une variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Nom de la variable.
+    pub variable: String,
+    /// Identifiant de la règle ([`Rule::id`]).
+    pub rule: &'static str,
+    /// L’échec, avec la mesure et la borne (voir [`ValidationError::actual`]
+    /// et [`ValidationError::limit`]).
+    pub error: ValidationError,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} [{}]", self.variable, self.error, self.rule)
+    }
+}
+
+/// Toutes les règles non respectées par un jeu de variables
+/// ([`Schema::report`]).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    violations: Vec<Violation>,
+}
+
+impl Report {
+    /// Les échecs, par variable puis par règle.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    /// Ajoute un échec constaté hors du schéma (une variable manquante, par
+    /// exemple).
+    pub fn push(&mut self, violation: Violation) {
+        self.violations.push(violation);
+    }
+
+    /// Ajoute les échecs de `other` après les siens.
+    pub fn merge(&mut self, other: Report) {
+        self.violations.extend(other.violations);
+    }
+
+    /// Aucun échec.
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// Le rapport en JSON :
+    /// `{"valid":false,"violations":[{"variable":…,"rule":…,"actual":…,"limit":…,"message":…}]}`.
+    /// `actual` est un nombre ou `null` ; `limit` un nombre, un texte, une
+    /// liste de textes (`one_of`) ou `null`. Les valeurs ne sont jamais
+    /// reprises : `actual` est une mesure (mots, caractères), il vaut `null`
+    /// pour `range` comme pour les règles qui ne mesurent rien.
+    pub fn to_json(&self) -> String {
+        let mut out = format!("{{\"valid\":{},\"violations\":[", self.is_valid());
+        let push_str = |out: &mut String, s: &str| {
+            out.push('"');
+            Escape::Json.push(out, s);
+            out.push('"');
+        };
+        for (i, v) in self.violations.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"variable\":");
+            push_str(&mut out, &v.variable);
+            out.push_str(",\"rule\":");
+            push_str(&mut out, v.rule);
+            out.push_str(",\"actual\":");
+            match v.error.actual() {
+                Some(n) => out.push_str(&n.to_string()),
+                None => out.push_str("null"),
+            }
+            out.push_str(",\"limit\":");
+            match v.error.limit() {
+                Some(Limit::Count(n)) => out.push_str(&n.to_string()),
+                Some(Limit::Integer(n)) => out.push_str(&n.to_string()),
+                Some(Limit::Text(t)) => push_str(&mut out, &t),
+                Some(Limit::OneOf(values)) => {
+                    out.push('[');
+                    for (j, value) in values.iter().enumerate() {
+                        if j > 0 {
+                            out.push(',');
+                        }
+                        push_str(&mut out, value);
+                    }
+                    out.push(']');
+                }
+                None => out.push_str("null"),
+            }
+            out.push_str(",\"message\":");
+            push_str(&mut out, &v.error.to_string());
+            out.push('}');
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+impl fmt::Display for Report {
+    /// Une ligne par échec.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for v in &self.violations {
+            writeln!(f, "{}", v)?;
+        }
         Ok(())
-    } else {
-        Err(ValidationError::TooManyWords(c, 40))
     }
 }
 
@@ -80,4 +800,251 @@ mod tests {
             Err(ValidationError::TooManyWords(_, 40))
         ));
     }
+
+    #[test]
+    fn builtin_rules() {
+        assert_eq!(
+            MinWords(2).check("un"),
+            Err(ValidationError::TooFewWords(1, 2))
+        );
+        assert_eq!(
+            MaxChars(3).check("abcé"),
+            Err(ValidationError::TooManyChars(4, 3))
+        );
+        assert_eq!(
+            MinChars(1).check(""),
+            Err(ValidationError::TooFewChars(0, 1))
+        );
+        assert_eq!(OneOf(vec!["a".into()]).check("a"), Ok(()));
+        let range = IntRange {
+            min: Some(1),
+            max: Some(10),
+        };
+        assert_eq!(range.check("10"), Ok(()));
+        assert_eq!(range.check("0"), Err(ValidationError::TooSmall(1)));
+        assert_eq!(range.check("11"), Err(ValidationError::TooLarge(10)));
+        assert_eq!(
+            range.check("2.5"),
+            Err(ValidationError::WrongType("integer".into()))
+        );
+        assert_eq!(NonEmpty.check(" \n"), Err(ValidationError::Empty));
+    }
+
+    #[test]
+    fn semver_versions() {
+        for ok in [
+            "0.1.0",
+            "1.90.0",
+            "1.0.0-rc.1+build.5",
+            "2.0.0-x-y.0a",
+            "1.2.3+001",
+        ] {
+            assert_eq!(SemVer.check(ok), Ok(()), "{ok}");
+        }
+        for bad in [
+            "1.2",
+            "v1.2.3",
+            "01.2.3",
+            "1.2.3-01",
+            "1.2.3-",
+            "1.2.3+",
+            "1.2.3-a..b",
+        ] {
+            assert_eq!(
+                SemVer.check(bad),
+                Err(ValidationError::InvalidSemver),
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn spdx_expressions() {
+        for ok in [
+            "MIT",
+            "mit OR Apache-2.0",
+            "(MIT OR Apache-2.0) AND BSD-3-Clause",
+            "GPL-2.0-or-later WITH Classpath-exception-2.0",
+            "Apache-2.0 WITH LLVM-exception",
+            "Apache-1.0 OR CC-BY-2.0",
+            "MIT-CMU AND BSD-3-Clause-LBNL",
+            "GPL-2.0+",
+            "LicenseRef-Proprietary",
+            "DocumentRef-other:LicenseRef-x.1",
+        ] {
+            assert_eq!(Spdx.check(ok), Ok(()), "{ok}");
+        }
+        for bad in [
+            "",
+            "Apache 2",
+            "MIT OR",
+            "(MIT",
+            "MIT)",
+            "MIT WITH Foo-exception",
+            "LicenseRef-",
+            "MIT AND OR ISC",
+        ] {
+            assert_eq!(
+                Spdx.check(bad),
+                Err(ValidationError::UnknownLicense),
+                "{bad}"
+            );
+        }
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn pattern_rule() {
+        let p = Pattern::new(r"^[a-z][a-z0-9_]*$").unwrap();
+        assert_eq!(p.check("fill_prompt"), Ok(()));
+        assert_eq!(
+            p.check("Fill"),
+            Err(ValidationError::PatternMismatch(
+                r"^[a-z][a-z0-9_]*$".into()
+            ))
+        );
+        assert!(Pattern::new("(").is_err());
+    }
+
+    #[test]
+    fn schema_merge_replaces_per_variable() {
+        let mut schema = Schema::builtin();
+        let mut custom = Schema::new();
+        custom
+            .add("short_description", MaxWords(50))
+            .add("tone", NonEmpty);
+        schema.merge(custom);
+        assert_eq!(
+            schema.variables().collect::<Vec<_>>(),
+            ["short_description", "context_paragraph", "tone"]
+        );
+        let mut vars = HashMap::new();
+        vars.insert("short_description".to_string(), "mot ".repeat(40));
+        vars.insert("tone".to_string(), String::new());
+        assert_eq!(
+            schema.validate(&vars),
+            Err(("tone".to_string(), ValidationError::Empty))
+        );
+        assert_eq!(schema.rules("tone")[0].id(), "non_empty");
+        assert!(schema.rules("absent").is_empty());
+
+        // les limites historiques gardent l'ordre du CLI
+        vars.insert("context_paragraph".to_string(), "mot ".repeat(41));
+        let report = Schema::builtin().report(&vars);
+        let found: Vec<&str> = report
+            .violations()
+            .iter()
+            .map(|v| v.variable.as_str())
+            .collect();
+        assert_eq!(found, ["short_description", "context_paragraph"]);
+    }
+
+    #[test]
+    fn report_lists_every_violation() {
+        let mut schema = Schema::new();
+        schema
+            .add("title", MaxWords(2))
+            .add("title", MaxChars(5))
+            .add("count", crate::front_matter::VarType::Integer)
+            .add(
+                "count",
+                IntRange {
+                    min: Some(1),
+                    max: None,
+                },
+            )
+            .add("tone", OneOf(vec!["formal".into(), "\"casual\"".into()]))
+            .add("version", SemVer);
+        let vars = HashMap::from([
+            ("title".to_string(), "un titre long".to_string()),
+            ("count".to_string(), "x".to_string()),
+            ("tone".to_string(), "rude".to_string()),
+            ("version".to_string(), "1.0.0".to_string()),
+        ]);
+        let report = schema.report(&vars);
+        assert!(!report.is_valid());
+        let found: Vec<(&str, &str)> = report
+            .violations()
+            .iter()
+            .map(|v| (v.variable.as_str(), v.rule))
+            .collect();
+        // ordre d'ajout ; l'erreur de type de `count` masque l'intervalle
+        assert_eq!(
+            found,
+            [
+                ("title", "max_words"),
+                ("title", "max_chars"),
+                ("count", "type"),
+                ("tone", "one_of")
+            ]
+        );
+        assert_eq!(
+            report.violations()[0].to_string(),
+            "title: trop long: 3 mots (max 2) [max_words]"
+        );
+        assert_eq!(
+            report.to_json(),
+            concat!(
+                r#"{"valid":false,"violations":["#,
+                r#"{"variable":"title","rule":"max_words","actual":3,"limit":2,"message":"trop long: 3 mots (max 2)"},"#,
+                r#"{"variable":"title","rule":"max_chars","actual":13,"limit":5,"message":"trop long: 13 caractères (max 5)"},"#,
+                r#"{"variable":"count","rule":"type","actual":null,"limit":"integer","message":"type attendu: integer"},"#,
+                r#"{"variable":"tone","rule":"one_of","actual":null,"limit":["formal","\"casual\""],"message":"valeur non autorisée (attendu: formal, \"casual\")"}]}"#
+            )
+        );
+        assert_eq!(
+            Schema::new().report(&vars).to_json(),
+            r#"{"valid":true,"violations":[]}"#
+        );
+    }
+
+    #[test]
+    fn report_takes_missing_variables_and_hides_integers() {
+        let mut schema = Schema::new();
+        schema.add(
+            "pin",
+            IntRange {
+                min: Some(1000),
+                max: None,
+            },
+        );
+        let vars = HashMap::from([("pin".to_string(), "42".to_string())]);
+        let mut report = Report::default();
+        report.push(Violation {
+            variable: "topic".to_string(),
+            rule: "required",
+            error: ValidationError::Missing,
+        });
+        report.merge(schema.report(&vars));
+        // l'entier hors intervalle est la valeur elle-même : il n'est pas repris
+        assert_eq!(
+            report.to_json(),
+            concat!(
+                r#"{"valid":false,"violations":["#,
+                r#"{"variable":"topic","rule":"required","actual":null,"limit":null,"message":"variable requise manquante"},"#,
+                r#"{"variable":"pin","rule":"range","actual":null,"limit":1000,"message":"trop petit (min 1000)"}]}"#
+            )
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn schema_files_use_the_front_matter_fields() {
+        let toml = "[variables.version]\nsemver = true\n\n[variables.license]\nspdx = true\nnon_empty = true\n\n[variables.count]\ntype = \"integer\"\nmin = 1\nmax = 5\n";
+        let yaml = "variables:\n  version: {semver: true}\n  license: {spdx: true, non_empty: true}\n  count: {type: integer, min: 1, max: 5}\n";
+        for schema in [
+            Schema::parse(Format::Toml, toml).unwrap(),
+            Schema::parse(Format::Yaml, yaml).unwrap(),
+        ] {
+            let ids = |var| schema.rules(var).iter().map(|r| r.id()).collect::<Vec<_>>();
+            assert_eq!(ids("count"), ["type", "range"]);
+            assert_eq!(ids("license"), ["non_empty", "spdx"]);
+            assert_eq!(
+                schema.check("count", "6"),
+                Err(ValidationError::TooLarge(5))
+            );
+            assert_eq!(schema.check("version", "1.0.0"), Ok(()));
+        }
+        assert!(Schema::load(Path::new("schema.json")).is_err());
+    }
 }
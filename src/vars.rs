@@ -1,6 +1,98 @@
 //! Chargement de variables depuis JSON / YAML / TOML.
+//!
+//! La table racine `fill_prompt` est réservée aux réglages (elle n’est pas une
+//! variable) :
+//!
+//! ```toml
+//! crate_name = "fill_prompt"
+//! context_paragraph = "..."
+//!
+//! [fill_prompt]
+//! tagged = ["context_paragraph"]   # entoure la valeur de <context_paragraph>…</context_paragraph>
+//! ```
+//!
+//! Les variables secrètes (jetons, URL internes…) se déclarent avec le préfixe
+//! `secret:` ou dans la table racine `secrets` ; leur valeur n’apparaît ni dans
+//! `Debug` ni dans les messages d’erreur (voir [`crate::secret`]) :
+//!
+//! ```toml
+//! "secret:api_token" = "tok-123"
+//!
+//! [secrets]
+//! internal_url = "https://intra.example/api"
+//! ```
+//!
+//! Le texte lu est effacé après analyse, mais pas les arbres intermédiaires
+//! de `serde_json`, `serde_yaml` ou `toml` ni les tampons internes de ces
+//! analyseurs : une copie des valeurs peut rester dans la mémoire libérée.
+//! Les valeurs renvoyées sont des `String` ordinaires ; à l’appelant de les
+//! placer dans un [`ZeroizingVars`](crate::secret::ZeroizingVars).
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use crate::secret::{split_secret_key, RedactedVars};
+
+/// Nom de la table racine réservée aux réglages.
+pub const SETTINGS_KEY: &str = "fill_prompt";
+
+/// Nom de la table racine dont toutes les entrées sont des variables secrètes.
+/// Une valeur scalaire `secrets = "..."` reste une variable ordinaire.
+pub const SECRETS_KEY: &str = "secrets";
+
+/// Réglages par variable lus dans la table `fill_prompt`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VarSettings {
+    /// Variables à entourer de balises `<nom>…</nom>` au rendu.
+    pub tagged: BTreeSet<String>,
+}
+
+/// Contenu complet d’un fichier (ou d’une valeur inline) de variables.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct VarsFile {
+    /// Variables clé → valeur (scalaires convertis en chaîne), secrets compris,
+    /// préfixe `secret:` retiré.
+    pub vars: HashMap<String, String>,
+    /// Noms des variables secrètes.
+    pub secrets: BTreeSet<String>,
+    /// Réglages de la table `fill_prompt`, vides si absente.
+    pub settings: VarSettings,
+}
+
+impl VarsFile {
+    /// Ajoute une variable, secrète si la clé porte le préfixe `secret:`.
+    pub fn insert(&mut self, key: &str, value: String) {
+        let (name, secret) = split_secret_key(key);
+        if secret {
+            self.secrets.insert(name.to_string());
+        }
+        self.vars.insert(name.to_string(), value);
+    }
+
+    /// Ajoute une variable secrète.
+    pub fn insert_secret(&mut self, key: &str, value: String) {
+        let (name, _) = split_secret_key(key);
+        self.secrets.insert(name.to_string());
+        self.vars.insert(name.to_string(), value);
+    }
+}
+
+// Debug manuel : les valeurs secrètes sont masquées
+impl fmt::Debug for VarsFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VarsFile")
+            .field(
+                "vars",
+                &RedactedVars {
+                    vars: &self.vars,
+                    secrets: &self.secrets,
+                },
+            )
+            .field("secrets", &self.secrets)
+            .field("settings", &self.settings)
+            .finish()
+    }
+}
 
 #[cfg(feature = "serde")]
 mod with_serde {
@@ -8,13 +100,22 @@ mod with_serde {
     use anyhow::{anyhow, Context, Result};
     use std::fs;
     use std::path::Path;
+    use zeroize::Zeroizing;
 
     pub fn parse_vars_arg(arg: &str) -> Result<HashMap<String, String>> {
+        parse_vars_file(arg).map(|f| f.vars)
+    }
+
+    /// Comme `parse_vars_arg`, en conservant aussi la table de réglages `fill_prompt`.
+    pub fn parse_vars_file(arg: &str) -> Result<VarsFile> {
         let path = Path::new(arg);
 
         if path.exists() && path.is_file() {
-            let content = fs::read_to_string(path)
-                .with_context(|| format!("lecture du fichier de variables: {}", arg))?;
+            // le fichier peut contenir des secrets : effacé après analyse
+            let content = Zeroizing::new(
+                fs::read_to_string(path)
+                    .with_context(|| format!("lecture du fichier de variables: {}", arg))?,
+            );
             if let Some(ext) = path
                 .extension()
                 .and_then(|e| e.to_str())
@@ -34,7 +135,13 @@ mod with_serde {
         }
     }
 
-    fn parse_inline_try_all(s: &str) -> Result<HashMap<String, String>> {
+    /// Analyse `s` uniquement comme un objet JSON : jamais comme un chemin de
+    /// fichier ni en TOML / YAML (une ligne de `--batch`, par exemple).
+    pub fn parse_vars_json(s: &str) -> Result<VarsFile> {
+        parse_json(s)
+    }
+
+    fn parse_inline_try_all(s: &str) -> Result<VarsFile> {
         parse_json(s)
             .or_else(|_| parse_toml(s))
             .or_else(|_| parse_yaml(s))
@@ -46,27 +153,40 @@ mod with_serde {
             })
     }
 
-    fn parse_json(s: &str) -> Result<HashMap<String, String>> {
+    fn parse_json(s: &str) -> Result<VarsFile> {
         let v: serde_json::Value = serde_json::from_str(s).context("parse JSON: invalid JSON")?;
         value_to_map_json(&v)
     }
 
-    fn parse_yaml(s: &str) -> Result<HashMap<String, String>> {
+    fn parse_yaml(s: &str) -> Result<VarsFile> {
         let v: serde_yaml::Value = serde_yaml::from_str(s).context("parse YAML: invalid YAML")?;
         value_to_map_yaml(&v)
     }
 
-    fn parse_toml(s: &str) -> Result<HashMap<String, String>> {
+    fn parse_toml(s: &str) -> Result<VarsFile> {
         let v: toml::Value = s.parse().context("parse TOML: invalid TOML")?;
         value_to_map_toml(&v)
     }
 
-    fn value_to_map_json(v: &serde_json::Value) -> Result<HashMap<String, String>> {
+    fn value_to_map_json(v: &serde_json::Value) -> Result<VarsFile> {
         match v {
             serde_json::Value::Object(map) => {
-                let mut out = HashMap::with_capacity(map.len());
+                let mut out = VarsFile::default();
                 for (k, val) in map {
-                    out.insert(k.clone(), json_value_to_string(val)?);
+                    if k == SETTINGS_KEY {
+                        out.settings = settings_from_value(
+                            serde_json::from_value(val.clone())
+                                .context("table fill_prompt (JSON)")?,
+                        );
+                        continue;
+                    }
+                    if let (SECRETS_KEY, serde_json::Value::Object(secrets)) = (k.as_str(), val) {
+                        for (name, secret) in secrets {
+                            out.insert_secret(name, json_value_to_string(name, secret)?);
+                        }
+                        continue;
+                    }
+                    out.insert(k, json_value_to_string(k, val)?);
                 }
                 Ok(out)
             }
@@ -74,29 +194,59 @@ mod with_serde {
         }
     }
 
-    fn json_value_to_string(v: &serde_json::Value) -> Result<String> {
+    /// Forme sérialisée de la table `fill_prompt` (champs inconnus refusés).
+    #[derive(serde::Deserialize, Default)]
+    #[serde(default, deny_unknown_fields)]
+    struct RawSettings {
+        tagged: Vec<String>,
+    }
+
+    fn settings_from_value(raw: RawSettings) -> VarSettings {
+        VarSettings {
+            tagged: raw.tagged.into_iter().collect(),
+        }
+    }
+
+    // les messages nomment la clé, jamais la valeur (elle peut être secrète)
+    fn json_value_to_string(key: &str, v: &serde_json::Value) -> Result<String> {
         match v {
             serde_json::Value::String(s) => Ok(s.clone()),
             serde_json::Value::Number(n) => Ok(n.to_string()),
             serde_json::Value::Bool(b) => Ok(b.to_string()),
             serde_json::Value::Null => Ok(String::new()),
-            other => Err(anyhow!(
-                "valeur non scalaire pour la clé (JSON): {:?}",
-                other
-            )),
+            _ => Err(anyhow!("valeur non scalaire pour la clé `{}` (JSON)", key)),
         }
     }
 
-    fn value_to_map_yaml(v: &serde_yaml::Value) -> Result<HashMap<String, String>> {
+    fn value_to_map_yaml(v: &serde_yaml::Value) -> Result<VarsFile> {
         match v {
             serde_yaml::Value::Mapping(map) => {
-                let mut out = HashMap::with_capacity(map.len());
+                let mut out = VarsFile::default();
                 for (k, val) in map {
                     let key = match k {
                         serde_yaml::Value::String(s) => s.clone(),
                         _ => return Err(anyhow!("clé YAML non-string")),
                     };
-                    out.insert(key, yaml_value_to_string(val)?);
+                    if key == SETTINGS_KEY {
+                        out.settings = settings_from_value(
+                            serde_yaml::from_value(val.clone())
+                                .context("table fill_prompt (YAML)")?,
+                        );
+                        continue;
+                    }
+                    if let (SECRETS_KEY, serde_yaml::Value::Mapping(secrets)) = (key.as_str(), val)
+                    {
+                        for (name, secret) in secrets {
+                            let name = match name {
+                                serde_yaml::Value::String(s) => s,
+                                _ => return Err(anyhow!("clé YAML non-string")),
+                            };
+                            out.insert_secret(name, yaml_value_to_string(name, secret)?);
+                        }
+                        continue;
+                    }
+                    let value = yaml_value_to_string(&key, val)?;
+                    out.insert(&key, value);
                 }
                 Ok(out)
             }
@@ -104,25 +254,34 @@ mod with_serde {
         }
     }
 
-    fn yaml_value_to_string(v: &serde_yaml::Value) -> Result<String> {
+    fn yaml_value_to_string(key: &str, v: &serde_yaml::Value) -> Result<String> {
         match v {
             serde_yaml::Value::String(s) => Ok(s.clone()),
             serde_yaml::Value::Bool(b) => Ok(b.to_string()),
             serde_yaml::Value::Number(n) => Ok(n.to_string()),
             serde_yaml::Value::Null => Ok(String::new()),
-            other => Err(anyhow!(
-                "valeur non scalaire pour la clé (YAML): {:?}",
-                other
-            )),
+            _ => Err(anyhow!("valeur non scalaire pour la clé `{}` (YAML)", key)),
         }
     }
 
-    fn value_to_map_toml(v: &toml::Value) -> Result<HashMap<String, String>> {
+    fn value_to_map_toml(v: &toml::Value) -> Result<VarsFile> {
         match v {
             toml::Value::Table(map) => {
-                let mut out = HashMap::with_capacity(map.len());
+                let mut out = VarsFile::default();
                 for (k, val) in map {
-                    out.insert(k.clone(), toml_value_to_string(val)?);
+                    if k == SETTINGS_KEY {
+                        out.settings = settings_from_value(
+                            val.clone().try_into().context("table fill_prompt (TOML)")?,
+                        );
+                        continue;
+                    }
+                    if let (SECRETS_KEY, toml::Value::Table(secrets)) = (k.as_str(), val) {
+                        for (name, secret) in secrets {
+                            out.insert_secret(name, toml_value_to_string(name, secret)?);
+                        }
+                        continue;
+                    }
+                    out.insert(k, toml_value_to_string(k, val)?);
                 }
                 Ok(out)
             }
@@ -130,7 +289,7 @@ mod with_serde {
         }
     }
 
-    fn toml_value_to_string(v: &toml::Value) -> Result<String> {
+    fn toml_value_to_string(key: &str, v: &toml::Value) -> Result<String> {
         match v {
             toml::Value::String(s) => Ok(s.clone()),
             toml::Value::Integer(i) => Ok(i.to_string()),
@@ -138,7 +297,7 @@ mod with_serde {
             toml::Value::Boolean(b) => Ok(b.to_string()),
             toml::Value::Datetime(dt) => Ok(dt.to_string()),
             toml::Value::Array(_) | toml::Value::Table(_) => {
-                Err(anyhow!("valeur non scalaire pour la clé (TOML)"))
+                Err(anyhow!("valeur non scalaire pour la clé `{}` (TOML)", key))
             }
         }
     }
@@ -149,15 +308,23 @@ mod without_serde {
     use super::*;
     use anyhow::{anyhow, Result};
 
-    pub fn parse_vars_arg(_arg: &str) -> Result<HashMap<String, String>> {
+    pub fn parse_vars_arg(arg: &str) -> Result<HashMap<String, String>> {
+        parse_vars_file(arg).map(|f| f.vars)
+    }
+
+    pub fn parse_vars_file(_arg: &str) -> Result<VarsFile> {
         Err(anyhow!(
             "feature \"serde\" non activée. Recompilez avec `--features serde`."
         ))
     }
+
+    pub fn parse_vars_json(s: &str) -> Result<VarsFile> {
+        parse_vars_file(s)
+    }
 }
 
 // re-exports publics (un seul point d’export)
 #[cfg(feature = "serde")]
-pub use with_serde::parse_vars_arg;
+pub use with_serde::{parse_vars_arg, parse_vars_file, parse_vars_json};
 #[cfg(not(feature = "serde"))]
-pub use without_serde::parse_vars_arg;
+pub use without_serde::{parse_vars_arg, parse_vars_file, parse_vars_json};
@@ -33,3 +33,35 @@ proptest! {
         prop_assert_eq!(err, fill_prompt::FillError::MissingVariable(key));
     }
 }
+
+proptest! {
+    // `render_with_options` tries a single-pass fast path before the full
+    // engine: whatever the input, both must agree, errors included
+    #[test]
+    fn fast_path_matches_template_render(
+        template in "[{}ab é|\\-_.\\[\\]<>$/\n]{0,24}",
+        permissive in any::<bool>(),
+        expand in any::<bool>(),
+        value in "[{}a ]{0,4}",
+    ) {
+        let vars = HashMap::from([
+            ("a".to_string(), value),
+            ("b".to_string(), "B".to_string()),
+            ("é".to_string(), "E".to_string()),
+            ("a b".to_string(), "AB".to_string()),
+        ]);
+        let options = fill_prompt::FillOptions {
+            key_syntax: if permissive {
+                fill_prompt::KeySyntax::Permissive
+            } else {
+                fill_prompt::KeySyntax::Strict
+            },
+            expand_values: expand,
+            ..Default::default()
+        };
+        let fast = fill_prompt::render_with_options(&template, &vars, &options);
+        let full = fill_prompt::Template::parse_with(&template, &options)
+            .and_then(|t| t.render_lookup(&vars));
+        prop_assert_eq!(fast, full);
+    }
+}
@@ -1,6 +1,6 @@
 #[cfg(feature = "serde")]
 mod with_serde {
-    use fill_prompt::vars::parse_vars_arg;
+    use fill_prompt::vars::{parse_vars_arg, parse_vars_file};
     use std::collections::HashMap;
     use std::fs;
     use std::path::PathBuf;
@@ -12,6 +12,7 @@ mod with_serde {
         PathBuf::from(VAR_FIXTURES).join(path)
     }
 
+    #[allow(clippy::manual_pattern_char_comparison)]
     fn value_trimmed<'a>(map: &'a HashMap<String, String>, key: &str) -> Option<&'a str> {
         map.get(key)
             .map(|s| s.trim_end_matches(|c| c == '\n' || c == '\r'))
@@ -76,6 +77,55 @@ mod with_serde {
         }
     }
 
+    #[test]
+    fn settings_table_is_not_a_variable() {
+        let arg = r#"
+            context_paragraph = "Utile"
+
+            [fill_prompt]
+            tagged = ["context_paragraph"]
+        "#;
+        let file = parse_vars_file(arg).expect("parse inline toml with settings");
+        assert_eq!(file.vars.len(), 1);
+        assert!(file.settings.tagged.contains("context_paragraph"));
+
+        let arg = r#"{"a": "1", "fill_prompt": {"tagged": ["a"]}}"#;
+        let file = parse_vars_file(arg).expect("parse inline json with settings");
+        assert!(!file.vars.contains_key("fill_prompt"));
+        assert!(file.settings.tagged.contains("a"));
+
+        let err = parse_vars_file(r#"{"fill_prompt": {"taged": ["a"]}}"#)
+            .expect_err("unknown settings field");
+        assert!(
+            format!("{err:#}").contains("table fill_prompt"),
+            "unexpected error: {err:#}"
+        );
+    }
+
+    #[test]
+    fn secrets_by_prefix_or_table_are_hidden_from_debug() {
+        let arg = r#"
+            author = "Max"
+            "secret:api_token" = "tok-123"
+
+            [secrets]
+            internal_url = "https://intra.example/api"
+        "#;
+        let file = parse_vars_file(arg).expect("parse inline toml with secrets");
+        assert_eq!(value_trimmed(&file.vars, "api_token"), Some("tok-123"));
+        assert_eq!(
+            file.secrets.iter().map(String::as_str).collect::<Vec<_>>(),
+            ["api_token", "internal_url"]
+        );
+        let shown = format!("{:?}", file);
+        assert!(shown.contains("\"author\": \"Max\""), "{shown}");
+        assert!(!shown.contains("tok-123") && !shown.contains("intra.example"));
+
+        let err = parse_vars_file(r#"{"secrets": {"token": ["tok-123"]}}"#)
+            .expect_err("non-scalar secret");
+        assert!(!format!("{err:#}").contains("tok-123"), "{err:#}");
+    }
+
     #[test]
     fn parse_inline_invalid_reports_error() {
         let err = parse_vars_arg("not-valid").expect_err("should fail to parse inline junk");